@@ -5,8 +5,11 @@ fn send(event_type: &EventType) {
     let delay = time::Duration::from_millis(20);
     match simulate(event_type) {
         Ok(()) => (),
-        Err(SimulateError) => {
-            println!("We could not send {:?}", event_type);
+        Err(SimulateError::UnmappedKey(key)) => {
+            println!("{:?} has no key code here", key);
+        }
+        Err(error) => {
+            println!("We could not send {:?}: {}", event_type, error);
         }
     }
     // Let ths OS catchup (at least MacOS)
@@ -0,0 +1,590 @@
+//! Common mouse actions, built on `simulate`.
+//!
+//! ```no_run
+//! use rdev::automation::{click_at, drag, scroll_at};
+//! use rdev::Button;
+//!
+//! click_at(100.0, 200.0, Button::Left).unwrap();
+//! drag((100.0, 200.0), (400.0, 200.0), Button::Left, 20).unwrap();
+//! scroll_at(400.0, 300.0, 0, -3).unwrap();
+//! ```
+//!
+//! `move_mouse_smoothly` glides the pointer instead of teleporting it, and
+//! `mouse_path` gives the same moves as `Step`s, to replay or save them.
+//!
+//! The same actions are available on `Automation`, which can send through
+//! any `Backend`, a mock one in tests for instance.
+use crate::rdev::{Button, EventType, SimulateError};
+use crate::sequence::{clock_seed, Rng, Step};
+use crate::simulate;
+use std::thread;
+use std::time::Duration;
+
+/// When the OS can't tell us (Linux).
+const DEFAULT_DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+/// Between two events, to let the OS catch up (at least macOS).
+const DEFAULT_DELAY: Duration = Duration::from_millis(20);
+/// Caps how many moves a motion has, however long and fast it is.
+const MAX_MOTION_STEPS: f64 = 100_000.0;
+
+/// Where `Automation` sends its events.
+pub trait Backend {
+    fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError>;
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    /// The longest time between two clicks for them to make a double-click.
+    fn double_click_interval(&mut self) -> Duration {
+        DEFAULT_DOUBLE_CLICK_INTERVAL
+    }
+
+    /// Where the pointer is, if it can be known.
+    fn position(&mut self) -> Option<(f64, f64)> {
+        None
+    }
+}
+
+/// Sends events with `simulate`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct System;
+
+impl Backend for System {
+    fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        simulate(event_type)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn double_click_interval(&mut self) -> Duration {
+        crate::windows::double_click_interval()
+    }
+
+    #[cfg(target_os = "macos")]
+    fn double_click_interval(&mut self) -> Duration {
+        crate::macos::double_click_interval()
+    }
+
+    fn position(&mut self) -> Option<(f64, f64)> {
+        crate::_mouse_position()
+    }
+}
+
+/// How the pointer gets from one point to another, see `MotionOptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    /// A straight line at constant speed.
+    Linear,
+    /// A straight line, speeding up then slowing down.
+    EaseInOut,
+    /// A random arc, speeding up then slowing down.
+    Bezier,
+    /// A random arc, with the speed profile of a hand movement (minimum
+    /// jerk): slower to start and to stop than `Bezier`.
+    Human,
+}
+
+impl Curve {
+    /// How far along the path we are at time `t`, both going from 0 to 1.
+    fn progress(self, t: f64) -> f64 {
+        match self {
+            Curve::Linear => t,
+            Curve::EaseInOut | Curve::Bezier => t * t * (3.0 - 2.0 * t),
+            Curve::Human => t * t * t * (10.0 + t * (6.0 * t - 15.0)),
+        }
+    }
+
+    /// How far the arc bends away from the straight line, as a fraction of
+    /// its length.
+    fn bend(self, rng: &mut Rng) -> (f64, f64) {
+        match self {
+            Curve::Linear | Curve::EaseInOut => (0.0, 0.0),
+            Curve::Bezier | Curve::Human => {
+                let bend = 0.3 * rng.next_signed();
+                (bend, bend * 0.5 + 0.1 * rng.next_signed())
+            }
+        }
+    }
+}
+
+/// How `mouse_path` moves the pointer.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MotionOptions {
+    pub curve: Curve,
+    /// Mouse moves per second.
+    pub rate: f64,
+    /// Up to how many pixels each intermediate point is randomly moved by.
+    /// The last one is always exactly on the target.
+    pub jitter: f64,
+    /// How far to go past the target before coming back to it, as a fraction
+    /// of the distance: 0.1 overshoots a 500 pixels move by 50 pixels.
+    pub overshoot: f64,
+    /// Seed for the arcs and jitter, to replay the exact same path. `None`
+    /// seeds from the clock.
+    pub seed: Option<u64>,
+}
+
+impl Default for MotionOptions {
+    fn default() -> MotionOptions {
+        MotionOptions {
+            curve: Curve::EaseInOut,
+            rate: 100.0,
+            jitter: 0.0,
+            overshoot: 0.0,
+            seed: None,
+        }
+    }
+}
+
+/// The `MouseMove` steps to go from `from` to `to` in `duration`, one every
+/// `1 / options.rate` seconds.
+///
+/// ```no_run
+/// use rdev::automation::{mouse_path, Curve, MotionOptions};
+/// use rdev::simulate_sequence;
+/// use std::time::Duration;
+///
+/// let mut options = MotionOptions::default();
+/// options.curve = Curve::Human;
+/// options.overshoot = 0.05;
+/// let steps = mouse_path((0.0, 0.0), (800.0, 600.0), Duration::from_millis(400), &options);
+/// simulate_sequence(&steps).unwrap();
+/// ```
+pub fn mouse_path(
+    from: (f64, f64),
+    to: (f64, f64),
+    duration: Duration,
+    options: &MotionOptions,
+) -> Vec<Step> {
+    let mut rng = Rng::new(options.seed.unwrap_or_else(clock_seed));
+    let count = (duration.as_secs_f64() * options.rate).round();
+    // Also catches a NaN rate
+    let count = if count >= 1.0 {
+        count.min(MAX_MOTION_STEPS) as u32
+    } else {
+        1
+    };
+    let delay = duration / count;
+
+    // The main movement ends past `to` when overshooting, the last fifth of
+    // the steps then come back
+    let overshoot = options.overshoot.max(0.0);
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let target = (to.0 + dx * overshoot, to.1 + dy * overshoot);
+    let back = if overshoot > 0.0 && count > 1 {
+        (count / 5).max(1)
+    } else {
+        0
+    };
+    let main = count - back;
+
+    // Control points a third and two thirds of the way, moved sideways
+    let (dx, dy) = (target.0 - from.0, target.1 - from.1);
+    let (bend1, bend2) = options.curve.bend(&mut rng);
+    let control1 = (
+        from.0 + dx / 3.0 - dy * bend1,
+        from.1 + dy / 3.0 + dx * bend1,
+    );
+    let control2 = (
+        from.0 + dx * 2.0 / 3.0 - dy * bend2,
+        from.1 + dy * 2.0 / 3.0 + dx * bend2,
+    );
+
+    let jitter = options.jitter.max(0.0);
+    (1..=count)
+        .map(|step| {
+            let (x, y) = if step == count {
+                to
+            } else {
+                let (x, y) = if step <= main {
+                    let t = options.curve.progress(f64::from(step) / f64::from(main));
+                    bezier(from, control1, control2, target, t)
+                } else {
+                    let t = Curve::EaseInOut.progress(f64::from(step - main) / f64::from(back));
+                    (
+                        target.0 + (to.0 - target.0) * t,
+                        target.1 + (to.1 - target.1) * t,
+                    )
+                };
+                (
+                    x + jitter * rng.next_signed(),
+                    y + jitter * rng.next_signed(),
+                )
+            };
+            Step::new(EventType::MouseMove { x, y }, delay)
+        })
+        .collect()
+}
+
+/// The point at `t` on the cubic Bézier curve going from `p0` to `p3`.
+fn bezier(p0: (f64, f64), p1: (f64, f64), p2: (f64, f64), p3: (f64, f64), t: f64) -> (f64, f64) {
+    let u = 1.0 - t;
+    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    (
+        a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+        a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+    )
+}
+
+/// Mouse actions sent through `backend`, waiting `delay` after each event.
+pub struct Automation<B: Backend> {
+    pub backend: B,
+    pub delay: Duration,
+}
+
+impl Automation<System> {
+    /// Sends through `simulate`.
+    pub fn system() -> Automation<System> {
+        Automation::new(System)
+    }
+}
+
+impl<B: Backend> Automation<B> {
+    pub fn new(backend: B) -> Automation<B> {
+        Automation {
+            backend,
+            delay: DEFAULT_DELAY,
+        }
+    }
+
+    fn send(&mut self, event_type: EventType) -> Result<(), SimulateError> {
+        self.backend.send(&event_type)?;
+        self.backend.sleep(self.delay);
+        Ok(())
+    }
+
+    pub fn move_to(&mut self, x: f64, y: f64) -> Result<(), SimulateError> {
+        self.send(EventType::MouseMove { x, y })
+    }
+
+    /// Presses and releases `button` where the pointer is.
+    pub fn click(&mut self, button: Button) -> Result<(), SimulateError> {
+        self.send(EventType::ButtonPress(button))?;
+        self.send(EventType::ButtonRelease(button))
+    }
+
+    pub fn click_at(&mut self, x: f64, y: f64, button: Button) -> Result<(), SimulateError> {
+        self.move_to(x, y)?;
+        self.click(button)
+    }
+
+    /// Two clicks close enough to count as a double-click. On macOS,
+    /// `simulate` numbers the second one as a double-click for applications
+    /// (`kCGMouseEventClickState`), as long as the pointer stays put.
+    pub fn double_click(&mut self, button: Button) -> Result<(), SimulateError> {
+        let interval = self.backend.double_click_interval();
+        // Each click takes 2 delays, stay well within the interval
+        let delay = self.delay.min(interval / 8);
+        let previous = std::mem::replace(&mut self.delay, delay);
+        let result = self.click(button).and_then(|()| self.click(button));
+        self.delay = previous;
+        result
+    }
+
+    /// Presses `button` at `from`, moves to `to` in `steps` straight steps,
+    /// and releases it there.
+    pub fn drag(
+        &mut self,
+        from: (f64, f64),
+        to: (f64, f64),
+        button: Button,
+        steps: u32,
+    ) -> Result<(), SimulateError> {
+        self.move_to(from.0, from.1)?;
+        self.send(EventType::ButtonPress(button))?;
+        let steps = steps.max(1);
+        let moved = (1..=steps).try_for_each(|step| {
+            let t = f64::from(step) / f64::from(steps);
+            self.move_to(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+        });
+        // Never leave the button held
+        let released = self.send(EventType::ButtonRelease(button));
+        moved.and(released)
+    }
+
+    /// Moves the pointer from where it is to `to` over `duration`, along
+    /// `mouse_path`. Fails with `NoDisplay` when the backend can't tell where
+    /// the pointer is.
+    pub fn move_smoothly(
+        &mut self,
+        to: (f64, f64),
+        duration: Duration,
+        options: &MotionOptions,
+    ) -> Result<(), SimulateError> {
+        let from = self.backend.position().ok_or(SimulateError::NoDisplay)?;
+        for step in mouse_path(from, to, duration, options) {
+            self.backend.send(&step.event_type)?;
+            self.backend.sleep(step.delay);
+        }
+        Ok(())
+    }
+
+    /// Scrolls by `delta_x`, `delta_y` at `x`, `y`, see `EventType::Wheel`.
+    pub fn scroll_at(
+        &mut self,
+        x: f64,
+        y: f64,
+        delta_x: i64,
+        delta_y: i64,
+    ) -> Result<(), SimulateError> {
+        self.move_to(x, y)?;
+        self.send(EventType::Wheel { delta_x, delta_y })
+    }
+}
+
+pub fn click(button: Button) -> Result<(), SimulateError> {
+    Automation::system().click(button)
+}
+
+pub fn click_at(x: f64, y: f64, button: Button) -> Result<(), SimulateError> {
+    Automation::system().click_at(x, y, button)
+}
+
+pub fn double_click(button: Button) -> Result<(), SimulateError> {
+    Automation::system().double_click(button)
+}
+
+pub fn drag(
+    from: (f64, f64),
+    to: (f64, f64),
+    button: Button,
+    steps: u32,
+) -> Result<(), SimulateError> {
+    Automation::system().drag(from, to, button, steps)
+}
+
+pub fn scroll_at(x: f64, y: f64, delta_x: i64, delta_y: i64) -> Result<(), SimulateError> {
+    Automation::system().scroll_at(x, y, delta_x, delta_y)
+}
+
+/// Moves the pointer to `to` over `duration` along `curve`, with the other
+/// `MotionOptions` left to their defaults.
+///
+/// ```no_run
+/// use rdev::automation::{move_mouse_smoothly, Curve};
+/// use std::time::Duration;
+///
+/// move_mouse_smoothly((400.0, 300.0), Duration::from_millis(300), Curve::Human).unwrap();
+/// ```
+pub fn move_mouse_smoothly(
+    to: (f64, f64),
+    duration: Duration,
+    curve: Curve,
+) -> Result<(), SimulateError> {
+    let options = MotionOptions {
+        curve,
+        ..MotionOptions::default()
+    };
+    Automation::system().move_smoothly(to, duration, &options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mouse_path, Automation, Backend, Curve, MotionOptions};
+    use crate::rdev::{Button, EventType, SimulateError};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Mock {
+        events: Vec<EventType>,
+        slept: Vec<Duration>,
+        /// Fails to send this one
+        fail: Option<EventType>,
+    }
+
+    impl Backend for Mock {
+        fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+            if self.fail == Some(*event_type) {
+                return Err(SimulateError::UnsupportedEvent);
+            }
+            self.events.push(*event_type);
+            Ok(())
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.slept.push(duration);
+        }
+
+        fn double_click_interval(&mut self) -> Duration {
+            Duration::from_millis(80)
+        }
+
+        fn position(&mut self) -> Option<(f64, f64)> {
+            self.events
+                .iter()
+                .rev()
+                .find_map(|event_type| match event_type {
+                    EventType::MouseMove { x, y } => Some((*x, *y)),
+                    _ => None,
+                })
+        }
+    }
+
+    fn options(curve: Curve) -> MotionOptions {
+        MotionOptions {
+            curve,
+            seed: Some(42),
+            ..MotionOptions::default()
+        }
+    }
+
+    fn points(steps: &[crate::Step]) -> Vec<(f64, f64)> {
+        steps
+            .iter()
+            .map(|step| match step.event_type {
+                EventType::MouseMove { x, y } => (x, y),
+                _ => panic!("not a move: {:?}", step.event_type),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_click_at() {
+        let mut automation = Automation::new(Mock::default());
+        automation.click_at(10.0, 20.0, Button::Right).unwrap();
+        assert_eq!(
+            automation.backend.events,
+            vec![
+                EventType::MouseMove { x: 10.0, y: 20.0 },
+                EventType::ButtonPress(Button::Right),
+                EventType::ButtonRelease(Button::Right),
+            ]
+        );
+        assert_eq!(automation.backend.slept, vec![automation.delay; 3]);
+    }
+
+    #[test]
+    fn test_double_click() {
+        let mut automation = Automation::new(Mock::default());
+        automation.double_click(Button::Left).unwrap();
+        assert_eq!(automation.backend.events.len(), 4);
+        let slept: Duration = automation.backend.slept.iter().sum();
+        assert!(slept <= Duration::from_millis(40));
+        assert_eq!(automation.delay, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_drag() {
+        let mut automation = Automation::new(Mock::default());
+        automation
+            .drag((0.0, 0.0), (100.0, 50.0), Button::Left, 4)
+            .unwrap();
+        let events = &automation.backend.events;
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[1], EventType::ButtonPress(Button::Left));
+        assert_eq!(events[3], EventType::MouseMove { x: 50.0, y: 25.0 });
+        assert_eq!(events[5], EventType::MouseMove { x: 100.0, y: 50.0 });
+        assert_eq!(events[6], EventType::ButtonRelease(Button::Left));
+
+        // The button is released even when moving fails
+        let mut automation = Automation::new(Mock {
+            fail: Some(EventType::MouseMove { x: 50.0, y: 0.0 }),
+            ..Mock::default()
+        });
+        let result = automation.drag((0.0, 0.0), (100.0, 0.0), Button::Left, 2);
+        assert!(result.is_err());
+        assert_eq!(
+            automation.backend.events.last(),
+            Some(&EventType::ButtonRelease(Button::Left))
+        );
+    }
+
+    #[test]
+    fn test_scroll_at() {
+        let mut automation = Automation::new(Mock::default());
+        automation.scroll_at(5.0, 6.0, 0, -3).unwrap();
+        assert_eq!(
+            automation.backend.events,
+            vec![
+                EventType::MouseMove { x: 5.0, y: 6.0 },
+                EventType::Wheel {
+                    delta_x: 0,
+                    delta_y: -3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_linear_path() {
+        let duration = Duration::from_millis(100);
+        let steps = mouse_path((0.0, 0.0), (100.0, 50.0), duration, &options(Curve::Linear));
+        assert_eq!(steps.len(), 10);
+        assert!(steps
+            .iter()
+            .all(|step| step.delay == Duration::from_millis(10)));
+        for (idx, (x, y)) in points(&steps).into_iter().enumerate() {
+            let expected = (idx + 1) as f64 * 10.0;
+            assert!((x - expected).abs() < 1e-9 && (y - expected / 2.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_curved_paths() {
+        let duration = Duration::from_millis(500);
+        for curve in [Curve::EaseInOut, Curve::Bezier, Curve::Human] {
+            let steps = mouse_path((10.0, 10.0), (510.0, 10.0), duration, &options(curve));
+            assert_eq!(steps.len(), 50);
+            assert_eq!(
+                steps,
+                mouse_path((10.0, 10.0), (510.0, 10.0), duration, &options(curve))
+            );
+            let points = points(&steps);
+            assert_eq!(points.last(), Some(&(510.0, 10.0)));
+            // Slow at both ends, fast in the middle
+            let first = points[0].0 - 10.0;
+            let middle = points[25].0 - points[24].0;
+            let last = 510.0 - points[48].0;
+            assert!(first < middle && last < middle, "{:?}", curve);
+            assert!(points.windows(2).all(|pair| pair[1].0 >= pair[0].0));
+            let straight = points.iter().all(|(_, y)| (y - 10.0).abs() < 1e-9);
+            assert_eq!(straight, curve == Curve::EaseInOut);
+        }
+    }
+
+    #[test]
+    fn test_overshoot_and_jitter() {
+        let mut options = options(Curve::EaseInOut);
+        options.overshoot = 0.1;
+        let duration = Duration::from_millis(200);
+        let overshot = points(&mouse_path((0.0, 0.0), (100.0, 0.0), duration, &options));
+        assert_eq!(overshot.len(), 20);
+        assert!((overshot[15].0 - 110.0).abs() < 1e-9);
+        assert_eq!(overshot[19], (100.0, 0.0));
+
+        options.overshoot = 0.0;
+        options.jitter = 2.0;
+        let jittered = points(&mouse_path((0.0, 0.0), (100.0, 0.0), duration, &options));
+        assert!(jittered[..19].iter().any(|(_, y)| *y != 0.0));
+        assert!(jittered.iter().all(|(_, y)| y.abs() <= 2.0));
+        assert_eq!(jittered[19], (100.0, 0.0));
+
+        // Never less than one step
+        let points = points(&mouse_path(
+            (0.0, 0.0),
+            (5.0, 5.0),
+            Duration::ZERO,
+            &options,
+        ));
+        assert_eq!(points, vec![(5.0, 5.0)]);
+    }
+
+    #[test]
+    fn test_move_smoothly() {
+        let mut automation = Automation::new(Mock::default());
+        let duration = Duration::from_millis(100);
+        let options = options(Curve::Human);
+        assert!(automation
+            .move_smoothly((10.0, 10.0), duration, &options)
+            .is_err());
+        automation.move_to(0.0, 0.0).unwrap();
+        automation
+            .move_smoothly((10.0, 10.0), duration, &options)
+            .unwrap();
+        assert_eq!(automation.backend.events.len(), 11);
+        assert_eq!(automation.backend.position(), Some((10.0, 10.0)));
+        let slept: Duration = automation.backend.slept[1..].iter().sum();
+        assert_eq!(slept, duration);
+    }
+}
@@ -37,7 +37,9 @@
 //! `listen` calleback and will not trigger it with events. No error will be generated.
 //!
 //! ## Linux
-//! The `listen` function uses X11 APIs, and so will not work in Wayland or in the linux kernel virtual console
+//! The `listen` function uses X11 APIs, and so will not work in Wayland or in the linux kernel virtual console.
+//! With the `evdev` feature, `listen` reads /dev/input instead when there is no X server (or in a Wayland
+//! session), see `listen_with_options`. Like `grab`, this needs read access to the input devices.
 //!
 //! # Sending some events
 //!
@@ -49,8 +51,11 @@
 //!     let delay = time::Duration::from_millis(20);
 //!     match simulate(event_type) {
 //!         Ok(()) => (),
-//!         Err(SimulateError) => {
-//!             println!("We could not send {:?}", event_type);
+//!         Err(SimulateError::UnmappedKey(key)) => {
+//!             println!("{:?} has no key code here", key);
+//!         }
+//!         Err(error) => {
+//!             println!("We could not send {:?}: {}", event_type, error);
 //!         }
 //!     }
 //!     // Let ths OS catchup (at least MacOS)
@@ -89,13 +94,15 @@
 //!     pub time: SystemTime,
 //!     pub name: Option<String>,
 //!     pub event_type: EventType,
+//!     /// Injected by a program, `simulate` for instance
+//!     pub synthetic: bool,
 //! }
 //! ```
 //!
 //! Be careful, Event::name, might be None, but also String::from(""), and might contain
 //! not displayable unicode characters. We send exactly what the OS sends us so do some sanity checking
 //! before using it.
-//! Caveat: Dead keys don't function yet on Linux
+//! Caveat: On Linux, dead keys and Compose sequences need the `xkb` feature.
 //!
 //! ## EventType
 //!
@@ -126,6 +133,11 @@
 //!         x: f64,
 //!         y: f64,
 //!     },
+//!     /// Simulate only, moves the pointer from where it is
+//!     MouseMoveRelative {
+//!         dx: f64,
+//!         dy: f64,
+//!     },
 //!     /// Note: On Linux, there is no actual delta the actual values are ignored for delta_x
 //!     /// and we only look at the sign of delta_y to simulate wheelup or wheeldown.
 //!     Wheel {
@@ -153,7 +165,7 @@
 //! layout for now !
 //! Caveat : This is layout dependent. If your app needs to support
 //! layout switching don't use this !
-//! Caveat: On Linux, the dead keys mechanism is not implemented.
+//! Caveat: On Linux, dead keys and Compose sequences need the `xkb` feature.
 //! Caveat: Only shift and dead keys are implemented, Alt+unicode code on windows
 //! won't work.
 //!
@@ -165,6 +177,10 @@
 //! // string == Some("s")
 //! ```
 //!
+//! On Linux, the `xkb` feature adds `XkbKeyboard`, which needs no display: its
+//! keymap is compiled with libxkbcommon from layout names like `us`, `fr` or
+//! `de(nodeadkeys)`.
+//!
 //! # Grabbing global events. (Requires `unstable_grab` feature)
 //!
 //! Installing this library with the `unstable_grab` feature adds the `grab` function
@@ -208,6 +224,8 @@
 //!
 //! ### Linux
 //! The `grab` function use the `evdev` library to intercept events, so they will work with both X11 and Wayland
+//! By default, `grab` still asks the X server for key names and the screen size when it is reachable,
+//! use `grab_with_options` with `KeymapSource::Xkb` to get names from xkbcommon without any display.
 //! In order for this to work, the process runnign the `listen` or `grab` loop needs to either run as root (not recommended),
 //! or run as a user who's a member of the `input` group (recommended)
 //! Note: on some distros, the group name for evdev access is called `plugdev`, and on some systems, both groups can exist.
@@ -217,32 +235,48 @@
 //!
 //! Event data returned by the `listen` and `grab` functions can be serialized and de-serialized with
 //! Serde if you install this library with the `serialize` feature.
+pub mod automation;
 mod rdev;
 pub use crate::rdev::{
     Button, DisplayError, Event, EventType, GrabCallback, GrabError, Key, KeyboardState,
     ListenError, SimulateError,
 };
+mod sequence;
+pub use crate::sequence::{
+    simulate_chord, simulate_sequence, simulate_typing, typing_steps, HeldKeys, Step, TypingOptions,
+};
 
 #[cfg(target_os = "macos")]
 mod macos;
 #[cfg(target_os = "macos")]
 pub use crate::macos::Keyboard;
 #[cfg(target_os = "macos")]
-use crate::macos::{display_size as _display_size, listen as _listen, simulate as _simulate};
+use crate::macos::{
+    display_size as _display_size, listen as _listen, mouse_position as _mouse_position,
+    simulate as _simulate,
+};
 
 #[cfg(target_os = "linux")]
-mod linux;
+pub mod linux;
 #[cfg(target_os = "linux")]
 pub use crate::linux::Keyboard;
 #[cfg(target_os = "linux")]
-use crate::linux::{display_size as _display_size, listen as _listen, simulate as _simulate};
+use crate::linux::{
+    display_size as _display_size, listen as _listen, mouse_position as _mouse_position,
+    simulate as _simulate,
+};
+#[cfg(target_os = "linux")]
+pub use crate::linux::{SimulateBackend, Simulator};
 
 #[cfg(target_os = "windows")]
 mod windows;
 #[cfg(target_os = "windows")]
 pub use crate::windows::Keyboard;
 #[cfg(target_os = "windows")]
-use crate::windows::{display_size as _display_size, listen as _listen, simulate as _simulate};
+use crate::windows::{
+    display_size as _display_size, listen as _listen, mouse_position as _mouse_position,
+    simulate as _simulate,
+};
 
 /// Listening to global events. Caveat: On MacOS, you require the listen
 /// loop needs to be the primary app (no fork before) and need to have accessibility
@@ -272,8 +306,48 @@ where
     _listen(callback)
 }
 
+/// Linux only. Same as `listen`, but can leave out synthetic events, and
+/// with the `evdev` feature lets you choose the backend: XRecord, or reading
+/// /dev/input directly which works without any X server (Wayland, console,
+/// headless). Both emit the same `Event` values. With the `evdev` feature,
+/// `listen` uses `ListenBackend::Auto`.
+///
+/// ```no_run
+/// use rdev::{listen_with_options, Event, ListenOptions};
+///
+/// fn callback(event: Event) {
+///     println!("My callback {:?}", event);
+/// }
+/// fn main(){
+///     let mut options = ListenOptions::default();
+///     // Not what we simulate ourselves
+///     options.ignore_synthetic = true;
+///     // With the `evdev` feature:
+///     // options.backend = ListenBackend::Evdev;
+///     // options.keymap = KeymapSource::Xkb;
+///     // This will block.
+///     if let Err(error) = listen_with_options(options, callback) {
+///         println!("Error: {:?}", error)
+///     }
+/// }
+/// ```
+#[cfg(target_os = "linux")]
+pub fn listen_with_options<T>(options: ListenOptions, callback: T) -> Result<(), ListenError>
+where
+    T: FnMut(Event) + 'static,
+{
+    crate::linux::listen_with_options(options, callback)
+}
+
 /// Sending some events
 ///
+/// On Linux this goes through XTest, and so needs an X server. With the
+/// `evdev` feature, outside of an X11 session (Wayland, console...) it goes
+/// through a virtual uinput keyboard+mouse instead. Each call waits for the
+/// event to be processed, use a `Simulator` to send lots of events quickly.
+/// `simulate_sequence` sends events with a delay after each one, and
+/// `typing_steps` gives these delays a human typing rhythm.
+///
 /// ```no_run
 /// use rdev::{simulate, Button, EventType, Key, SimulateError};
 /// use std::{thread, time};
@@ -282,8 +356,11 @@ where
 ///     let delay = time::Duration::from_millis(20);
 ///     match simulate(event_type) {
 ///         Ok(()) => (),
-///         Err(SimulateError) => {
-///             println!("We could not send {:?}", event_type);
+///         Err(SimulateError::UnmappedKey(key)) => {
+///             println!("{:?} has no key code here", key);
+///         }
+///         Err(error) => {
+///             println!("We could not send {:?}: {}", event_type, error);
 ///         }
 ///     }
 ///     // Let ths OS catchup (at least MacOS)
@@ -308,6 +385,40 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
     _simulate(event_type)
 }
 
+/// Linux only. Types `text` with the current keyboard layout, pressing Shift
+/// or AltGr when a character needs them. Characters the layout can't produce
+/// at all are typed by mapping them to an unused keycode for a moment, the
+/// keymap is restored before returning. Needs an X server.
+///
+/// ```no_run
+/// use rdev::simulate_text;
+///
+/// simulate_text("Grüße 🙂\n").unwrap();
+/// ```
+#[cfg(target_os = "linux")]
+pub fn simulate_text(text: &str) -> Result<(), SimulateError> {
+    crate::linux::simulate_text(text)
+}
+
+/// Linux only. Opt-in, for the `simulate` and `simulate_text` calls of the
+/// current thread: modifiers the user is holding are released before sending,
+/// then pressed again if they are still held. Meant for hotkey handlers, where
+/// the hotkey's modifiers would otherwise apply to the simulated keys. Only
+/// works with X11, `simulate` fails with `UnsupportedEvent` when it goes
+/// through uinput, see `Simulator::set_clear_modifiers`.
+///
+/// ```no_run
+/// use rdev::{set_clear_modifiers, simulate_text};
+///
+/// set_clear_modifiers(true);
+/// // Typed as is, even with Ctrl still held from the hotkey
+/// simulate_text("hello").unwrap();
+/// ```
+#[cfg(target_os = "linux")]
+pub fn set_clear_modifiers(clear: bool) {
+    crate::linux::set_clear_modifiers(clear)
+}
+
 /// Returns the size in pixels of the main screen.
 /// This is useful to use with x, y from MouseMove Event.
 ///
@@ -338,6 +449,11 @@ pub use crate::windows::grab as _grab;
 /// loop needs to be the primary app (no fork before) and need to have accessibility
 /// settings enabled.
 /// On Linux, you need rw access to evdev devices in /etc/input/ (usually group membership in `input` group is enough)
+/// On Linux, holding Ctrl+Alt+Shift+Escape for 2 seconds always ends the grab and
+/// ungrabs every device, and so does a panic in the callback (see `EscapeChord`).
+/// On Linux, devices that can't be grabbed are skipped without a word, only
+/// `grab_with_options` returns which ones (see `GrabReport`). If none of them
+/// can be grabbed, this fails with `GrabError::DeviceError` for the last one.
 ///
 /// ```no_run
 /// use rdev::{grab, Event, EventType, Key};
@@ -364,6 +480,124 @@ where
     _grab(callback)
 }
 
+#[cfg(target_os = "linux")]
+pub use crate::linux::ListenOptions;
+#[cfg(feature = "xkb")]
+#[cfg(target_os = "linux")]
+pub use crate::linux::XkbKeyboard;
+#[cfg(feature = "unstable_grab")]
+#[cfg(target_os = "linux")]
+pub use crate::linux::{
+    DeviceReport, DeviceStatus, EscapeChord, GrabContext, GrabEvent, GrabOptions, GrabReport,
+    OutputMode,
+};
+#[cfg(feature = "evdev")]
+#[cfg(target_os = "linux")]
+pub use crate::linux::{KeymapSource, ListenBackend, PointerBounds};
+
+/// Linux only. Same as `grab`, but lets you choose where key names and the
+/// pointer position come from. With `KeymapSource::Xkb` and
+/// `PointerBounds::Unbounded` (or `PointerBounds::Fixed`), no X display is
+/// needed at all, so this works under Wayland, in a console or on a
+/// headless machine.
+/// Devices that can't be grabbed (not readable, or already grabbed by another
+/// program) are skipped unless `GrabOptions::strict` is set, and the returned
+/// `GrabReport` tells what happened to each of them.
+///
+/// ```no_run
+/// use rdev::{grab_with_options, Event, GrabOptions, KeymapSource, PointerBounds};
+///
+/// fn callback(event: Event) -> Option<Event> {
+///     println!("My callback {:?}", event);
+///     Some(event)
+/// }
+/// fn main(){
+///     let mut options = GrabOptions::default();
+///     options.keymap = KeymapSource::Xkb;
+///     options.bounds = PointerBounds::Unbounded;
+///     // This will block.
+///     match grab_with_options(options, callback) {
+///         Ok(report) => println!("Grabbed devices: {:?}", report.devices),
+///         Err(error) => println!("Error: {:?}", error),
+///     }
+/// }
+/// ```
+#[cfg(feature = "unstable_grab")]
+#[cfg(target_os = "linux")]
+pub fn grab_with_options<T>(options: GrabOptions, callback: T) -> Result<GrabReport, GrabError>
+where
+    T: Fn(Event) -> Option<Event> + 'static,
+{
+    crate::linux::grab_with_options(options, callback)
+}
+
+/// Linux only. Same as `grab_with_options`, but the callback also gets a
+/// `GrabContext`:
+///  - `GrabContext::emit` sends extra events through the grab's own uinput
+///    devices, in order with the events let through. Prefer it to `simulate`,
+///    which goes through the X server and doesn't work under Wayland.
+///  - `GrabContext::schedule` sets timers, to act when no input arrives
+///    (tap-vs-hold keys, chord timeouts, sequences expiring...). A timer calls
+///    the callback with `GrabEvent::Timer(token)`, and the event it returns
+///    then (if any) is sent.
+///
+/// ```no_run
+/// use rdev::{grab_with_context, Event, EventType, GrabEvent, GrabOptions, Key};
+/// use std::time::{Duration, SystemTime};
+///
+/// const HOLD: u64 = 1;
+///
+/// fn main(){
+///     // CapsLock is Escape when tapped, and Control when held for 200ms.
+///     let mut control = false;
+///     let result = grab_with_context(GrabOptions::default(), move |event, context| {
+///         match event {
+///             GrabEvent::Input(event) => match event.event_type {
+///                 EventType::KeyPress(Key::CapsLock) => {
+///                     if !control {
+///                         context.cancel(HOLD);
+///                         context.schedule(Duration::from_millis(200), HOLD);
+///                     }
+///                     None
+///                 }
+///                 EventType::KeyRelease(Key::CapsLock) => {
+///                     context.cancel(HOLD);
+///                     if control {
+///                         control = false;
+///                         context.emit(&EventType::KeyRelease(Key::ControlLeft)).ok();
+///                     } else {
+///                         context.emit(&EventType::KeyPress(Key::Escape)).ok();
+///                         context.emit(&EventType::KeyRelease(Key::Escape)).ok();
+///                     }
+///                     None
+///                 }
+///                 _ => Some(event),
+///             },
+///             GrabEvent::Timer(_) => {
+///                 control = true;
+///                 Some(Event {
+///                     time: SystemTime::now(),
+///                     name: None,
+///                     event_type: EventType::KeyPress(Key::ControlLeft),
+///                     synthetic: false,
+///                 })
+///             }
+///         }
+///     });
+///     if let Err(error) = result {
+///         println!("Error: {:?}", error)
+///     }
+/// }
+/// ```
+#[cfg(feature = "unstable_grab")]
+#[cfg(target_os = "linux")]
+pub fn grab_with_context<T>(options: GrabOptions, callback: T) -> Result<GrabReport, GrabError>
+where
+    T: FnMut(GrabEvent, &mut GrabContext) -> Option<Event> + 'static,
+{
+    crate::linux::grab_with_context(options, callback)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -423,4 +657,33 @@ mod tests {
         // assert_eq!(e, "é".to_string());
         // keyboard.add(&EventType::KeyRelease(Key::KeyE));
     }
+
+    #[test]
+    fn test_error_messages() {
+        use std::error::Error;
+        use std::io;
+
+        let error = GrabError::DeviceError {
+            path: "/dev/input/event3".into(),
+            error: io::Error::from_raw_os_error(16),
+        };
+        assert_eq!(error.to_string(), "Could not grab /dev/input/event3");
+        assert_eq!(
+            error.source().map(|source| source.to_string()),
+            Some(io::Error::from_raw_os_error(16).to_string())
+        );
+
+        let error = GrabError::from(SimulateError::UnmappedKey(Key::KeyA));
+        assert_eq!(error.to_string(), "Could not send the grabbed event");
+        assert_eq!(
+            error.source().map(|source| source.to_string()),
+            Some("Could not simulate event: KeyA has no key code".to_string())
+        );
+        assert_eq!(
+            ListenError::XRecordExtensionError.to_string(),
+            "The X server lacks the RECORD extension"
+        );
+        let boxed: Box<dyn Error> = Box::new(DisplayError::NoDisplay);
+        assert_eq!(boxed.to_string(), "No display found");
+    }
 }
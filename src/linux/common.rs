@@ -63,6 +63,7 @@ pub fn convert(
     type_: c_int,
     x: f64,
     y: f64,
+    synthetic: bool,
 ) -> Option<Event> {
     let event_type = convert_event(code as c_uchar, type_, x, y)?;
     let kb: &mut Keyboard = (*keyboard).as_mut()?;
@@ -71,6 +72,7 @@ pub fn convert(
         event_type,
         time: SystemTime::now(),
         name,
+        synthetic,
     })
 }
 
@@ -103,7 +105,6 @@ impl Display {
         }
     }
 
-    #[cfg(feature = "unstable_grab")]
     pub fn get_mouse_pos(&self) -> Option<(u64, u64)> {
         unsafe {
             let root_window = xlib::XRootWindow(self.display, 0);
@@ -129,6 +130,13 @@ impl Display {
         }
     }
 }
+/// Whether the X server is the one handling input. Under Wayland, XWayland
+/// only sees what goes to X clients.
+#[cfg(feature = "evdev")]
+pub fn is_x11_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_none() && Display::new().is_some()
+}
+
 impl Drop for Display {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,141 @@
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use xkbcommon::xkb;
+use xkbcommon::xkb::compose;
+
+/// Dead keys and Compose sequences, with the same tables applications use:
+/// the locale's Compose file, or `$XCOMPOSEFILE`/`~/.XCompose` when they
+/// exist (libxkbcommon picks them).
+pub struct Compose {
+    state: compose::State,
+}
+
+impl fmt::Debug for Compose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Compose")
+            .field("status", &self.state.status())
+            .finish()
+    }
+}
+
+impl Compose {
+    /// The table for the `LC_ALL`, `LC_CTYPE` or `LANG` locale, like
+    /// applications would use.
+    pub fn from_env() -> Option<Compose> {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(env::var_os)
+            .find(|locale| !locale.is_empty())
+            .unwrap_or_else(|| OsString::from("C"));
+        Compose::from_locale(&locale.to_string_lossy())
+    }
+
+    pub fn from_locale(locale: &str) -> Option<Compose> {
+        // xkbcommon panics on those
+        if locale.contains('\0') {
+            return None;
+        }
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let table =
+            compose::Table::new_from_locale(&context, locale.as_ref(), compose::COMPILE_NO_FLAGS)
+                .ok()?;
+        Some(Compose::new(&table))
+    }
+
+    fn new(table: &compose::Table) -> Compose {
+        Compose {
+            state: compose::State::new(table, compose::STATE_NO_FLAGS),
+        }
+    }
+
+    /// Feeds the keysym of a key press, and gives back what the application
+    /// would get: `name` outside of a sequence, nothing while composing, and
+    /// the result once it is complete.
+    pub fn feed(&mut self, keysym: u32, name: Option<String>) -> Option<String> {
+        let keysym = xkb::Keysym::new(keysym);
+        if keysym == xkb::Keysym::NoSymbol {
+            return name;
+        }
+        if self.state.feed(keysym) == compose::FeedResult::Ignored {
+            // Modifiers
+            return name;
+        }
+        match self.state.status() {
+            compose::Status::Nothing => name,
+            compose::Status::Composing => None,
+            compose::Status::Composed => {
+                let composed = self.state.utf8();
+                self.state.reset();
+                composed
+            }
+            compose::Status::Cancelled => {
+                // Applications drop the whole sequence
+                self.state.reset();
+                None
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Compose;
+    use xkbcommon::xkb;
+    use xkbcommon::xkb::compose;
+
+    const DEAD_ACUTE: u32 = 0xfe51;
+    const MULTI_KEY: u32 = 0xff20;
+    const SHIFT_L: u32 = 0xffe1;
+
+    fn compose() -> Compose {
+        let table = "<dead_acute> <e> : \"é\" eacute\n\
+                     <Multi_key> <o> <e> : \"œ\" oe\n";
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let table = compose::Table::new_from_buffer(
+            &context,
+            table,
+            "C",
+            compose::FORMAT_TEXT_V1,
+            compose::COMPILE_NO_FLAGS,
+        )
+        .unwrap();
+        Compose::new(&table)
+    }
+
+    #[test]
+    fn test_feed() {
+        let mut compose = compose();
+        let e = Some("e".to_string());
+        assert_eq!(compose.feed(0x65, e.clone()), e);
+        assert_eq!(compose.feed(DEAD_ACUTE, None), None);
+        assert_eq!(compose.feed(SHIFT_L, None), None);
+        assert_eq!(compose.feed(0x65, e.clone()), Some("é".to_string()));
+        assert_eq!(compose.feed(0x65, e.clone()), e);
+
+        assert_eq!(compose.feed(MULTI_KEY, None), None);
+        assert_eq!(compose.feed(0x6f, Some("o".to_string())), None);
+        assert_eq!(compose.feed(0x65, e.clone()), Some("œ".to_string()));
+
+        // Unknown sequence
+        assert_eq!(compose.feed(MULTI_KEY, None), None);
+        assert_eq!(compose.feed(0x78, Some("x".to_string())), None);
+        assert_eq!(compose.feed(0x65, e.clone()), e);
+
+        assert_eq!(compose.feed(DEAD_ACUTE, None), None);
+        compose.reset();
+        assert_eq!(compose.feed(0x65, e.clone()), e);
+    }
+
+    #[test]
+    fn test_from_locale() {
+        let mut compose = Compose::from_locale("en_US.UTF-8").unwrap();
+        assert_eq!(compose.feed(DEAD_ACUTE, None), None);
+        assert_eq!(compose.feed(0x65, None), Some("é".to_string()));
+        assert!(Compose::from_locale("C\0").is_none());
+    }
+}
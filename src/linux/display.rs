@@ -5,3 +5,9 @@ pub fn display_size() -> Result<(u64, u64), DisplayError> {
     let display = Display::new().ok_or(DisplayError::NoDisplay)?;
     display.get_size().ok_or(DisplayError::NoDisplay)
 }
+
+pub fn mouse_position() -> Option<(f64, f64)> {
+    let display = Display::new()?;
+    let (x, y) = display.get_mouse_pos()?;
+    Some((x as f64, y as f64))
+}
@@ -0,0 +1,51 @@
+//! Raw access to the evdev pipeline `grab` is built on.
+//!
+//! `EventType` only models keys, buttons, pointer motion and the wheel. Here the
+//! callback sees every `InputEvent` the grabbed devices send (`EV_SYN`,
+//! `EV_MSC`, `EV_ABS`, keys rdev has no name for...) before anything else does,
+//! and decides what gets written to the uinput output device:
+//!
+//!  - pass the event through by returning it,
+//!  - drop it by returning `None`,
+//!  - rewrite it by returning a modified event,
+//!  - inject extra events by writing them to the `Output` first.
+//!
+//! The devices are set up exactly like `grab_with_options` does, so the same
+//! `GrabOptions` apply, including the escape chord and the `GrabReport`.
+//!
+//! ```no_run
+//! use rdev::linux::evdev::{
+//!     enums::{EventCode, EV_KEY, EV_SYN},
+//!     filter_map_events, GrabStatus, InputEvent,
+//! };
+//! use rdev::GrabOptions;
+//!
+//! let result = filter_map_events(&GrabOptions::default(), |event, output| {
+//!     match event.event_code {
+//!         // Rewrite CapsLock into Escape
+//!         EventCode::EV_KEY(EV_KEY::KEY_CAPSLOCK) => {
+//!             let escape = InputEvent::new(&event.time, &EventCode::EV_KEY(EV_KEY::KEY_ESC), event.value);
+//!             (Some(escape), GrabStatus::Continue)
+//!         }
+//!         // Drop the Insert key entirely
+//!         EventCode::EV_KEY(EV_KEY::KEY_INSERT) => (None, GrabStatus::Continue),
+//!         // Type an extra "a" whenever F12 is pressed
+//!         EventCode::EV_KEY(EV_KEY::KEY_F12) if event.value == 1 => {
+//!             let sync = EventCode::EV_SYN(EV_SYN::SYN_REPORT);
+//!             for value in &[1, 0] {
+//!                 let a = InputEvent::new(&event.time, &EventCode::EV_KEY(EV_KEY::KEY_A), *value);
+//!                 output.write(&a).ok();
+//!                 output.write(&InputEvent::new(&event.time, &sync, 0)).ok();
+//!             }
+//!             (Some(event), GrabStatus::Continue)
+//!         }
+//!         _ => (Some(event), GrabStatus::Continue),
+//!     }
+//! });
+//! if let Err(error) = result {
+//!     println!("Error: {:?}", error)
+//! }
+//! ```
+pub use crate::linux::evdev_common::{device_paths, open_device};
+pub use crate::linux::grab::{create_merged_device, filter_map_events, GrabStatus, Output};
+pub use evdev_rs::{enums, Device, InputEvent, TimeVal, UInputDevice};
@@ -0,0 +1,665 @@
+//! What the evdev backends (`grab` and `listen`) have in common: finding and
+//! opening the devices, and turning their events into rdev events.
+use crate::linux::common::Display;
+use crate::linux::keyboard::Keyboard;
+use crate::linux::xkb::XkbKeyboard;
+use crate::rdev::{Button, EventType, Key, KeyboardState};
+use evdev_rs::{
+    enums::{int_to_ev_key, EventCode, EV_KEY, EV_REL, EV_SYN},
+    Device, InputEvent, TimeVal, UInputDevice,
+};
+use inotify::{Inotify, WatchMask};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::{read_dir, File};
+use std::io;
+use std::os::unix::{
+    ffi::OsStrExt,
+    fs::FileTypeExt,
+    io::{IntoRawFd, RawFd},
+};
+use std::path::{Path, PathBuf};
+
+// TODO The x, y coordinates are currently wrong !! Is there mouse acceleration
+// to take into account ??
+
+macro_rules! convert_keys {
+    ($($ev_key:ident, $rdev_key:ident),*) => {
+        //TODO: make const when rust lang issue #49146 is fixed
+        #[allow(unreachable_patterns)]
+        pub fn evdev_key_to_rdev_key(key: &EV_KEY) -> Option<Key> {
+            match key {
+                $(
+                    EV_KEY::$ev_key => Some(Key::$rdev_key),
+                )*
+                _ => None,
+            }
+        }
+
+        //TODO: make const when rust lang issue #49146 is fixed
+        pub fn rdev_key_to_evdev_key(key: &Key) -> Option<EV_KEY> {
+            match key {
+                $(
+                    Key::$rdev_key => Some(EV_KEY::$ev_key),
+                )*
+                _ => None
+            }
+        }
+    };
+}
+
+macro_rules! convert_buttons {
+    ($($ev_key:ident, $rdev_key:ident),*) => {
+        //TODO: make const when rust lang issue #49146 is fixed
+        pub fn evdev_key_to_rdev_button(key: &EV_KEY) -> Option<Button> {
+            match key {
+                $(
+                    EV_KEY::$ev_key => Some(Button::$rdev_key),
+                )*
+                _ => None,
+            }
+        }
+
+        //TODO: make const when rust lang issue #49146 is fixed
+        pub fn rdev_button_to_evdev_key(event: &Button) -> Option<EV_KEY> {
+            match event {
+                $(
+                    Button::$rdev_key => Some(EV_KEY::$ev_key),
+                )*
+                _ => None
+            }
+        }
+    };
+}
+
+#[rustfmt::skip]
+convert_buttons!(
+    BTN_LEFT, Left,
+    BTN_RIGHT, Right,
+    BTN_MIDDLE, Middle
+);
+
+#[rustfmt::skip]
+convert_keys!(
+    KEY_ESC, Escape,
+    KEY_1, Num1,
+    KEY_2, Num2,
+    KEY_3, Num3,
+    KEY_4, Num4,
+    KEY_5, Num5,
+    KEY_6, Num6,
+    KEY_7, Num7,
+    KEY_8, Num8,
+    KEY_9, Num9,
+    KEY_0, Num0,
+    KEY_MINUS, Minus,
+    KEY_EQUAL, Equal,
+    KEY_BACKSPACE, Backspace,
+    KEY_TAB, Tab,
+    KEY_Q, KeyQ,
+    KEY_W, KeyW,
+    KEY_E, KeyE,
+    KEY_R, KeyR,
+    KEY_T, KeyT,
+    KEY_Y, KeyY,
+    KEY_U, KeyU,
+    KEY_I, KeyI,
+    KEY_O, KeyO,
+    KEY_P, KeyP,
+    KEY_LEFTBRACE, LeftBracket,
+    KEY_RIGHTBRACE, RightBracket,
+    KEY_ENTER, Return,
+    KEY_LEFTCTRL, ControlLeft,
+    KEY_A, KeyA,
+    KEY_S, KeyS,
+    KEY_D, KeyD,
+    KEY_F, KeyF,
+    KEY_G, KeyG,
+    KEY_H, KeyH,
+    KEY_J, KeyJ,
+    KEY_K, KeyK,
+    KEY_L, KeyL,
+    KEY_SEMICOLON, SemiColon,
+    KEY_APOSTROPHE, Quote,
+    KEY_GRAVE, BackQuote,
+    KEY_LEFTSHIFT, ShiftLeft,
+    KEY_BACKSLASH, BackSlash,
+    KEY_Z, KeyZ,
+    KEY_X, KeyX,
+    KEY_C, KeyC,
+    KEY_V, KeyV,
+    KEY_B, KeyB,
+    KEY_N, KeyN,
+    KEY_M, KeyM,
+    KEY_COMMA, Comma,
+    KEY_DOT, Dot,
+    KEY_SLASH, Slash,
+    KEY_RIGHTSHIFT, ShiftRight,
+    KEY_KPASTERISK , KpMultiply,
+    KEY_LEFTALT, Alt,
+    KEY_SPACE, Space,
+    KEY_CAPSLOCK, CapsLock,
+    KEY_F1, F1,
+    KEY_F2, F2,
+    KEY_F3, F3,
+    KEY_F4, F4,
+    KEY_F5, F5,
+    KEY_F6, F6,
+    KEY_F7, F7,
+    KEY_F8, F8,
+    KEY_F9, F9,
+    KEY_F10, F10,
+    KEY_NUMLOCK, NumLock,
+    KEY_SCROLLLOCK, ScrollLock,
+    KEY_KP7, Kp7,
+    KEY_KP8, Kp8,
+    KEY_KP9, Kp9,
+    KEY_KPMINUS, KpMinus,
+    KEY_KP4, Kp4,
+    KEY_KP5, Kp5,
+    KEY_KP6, Kp6,
+    KEY_KPPLUS, KpPlus,
+    KEY_KP1, Kp1,
+    KEY_KP2, Kp2,
+    KEY_KP3, Kp3,
+    KEY_KP0, Kp0,
+    KEY_F11, F11,
+    KEY_F12, F12,
+    KEY_KPENTER, KpReturn,
+    KEY_RIGHTCTRL, ControlRight,
+    KEY_KPSLASH, KpDivide,
+    KEY_RIGHTALT, AltGr,
+    KEY_HOME , Home,
+    KEY_UP, UpArrow,
+    KEY_PAGEUP, PageUp,
+    KEY_LEFT, LeftArrow,
+    KEY_RIGHT, RightArrow,
+    KEY_END, End,
+    KEY_DOWN, DownArrow,
+    KEY_PAGEDOWN, PageDown,
+    KEY_INSERT, Insert,
+    KEY_DELETE, Delete,
+    KEY_PAUSE, Pause,
+    KEY_LEFTMETA, MetaLeft,
+    KEY_RIGHTMETA, MetaRight,
+    // The Print key sends KEY_SYSRQ, X11 agrees
+    KEY_SYSRQ, PrintScreen,
+    KEY_KPDOT, KpDelete,
+    KEY_102ND, IntlBackslash
+);
+
+pub fn evdev_event_to_rdev_event(
+    event: &InputEvent,
+    x: &mut f64,
+    y: &mut f64,
+    bounds: Option<(f64, f64)>,
+) -> Option<EventType> {
+    match &event.event_code {
+        EventCode::EV_KEY(key) => {
+            if let Some(button) = evdev_key_to_rdev_button(key) {
+                // first check if pressed key is a mouse button
+                match event.value {
+                    0 => Some(EventType::ButtonRelease(button)),
+                    _ => Some(EventType::ButtonPress(button)),
+                }
+            } else if let Some(key) = evdev_key_to_rdev_key(key) {
+                // check if pressed key is a keyboard key
+                match event.value {
+                    0 => Some(EventType::KeyRelease(key)),
+                    _ => Some(EventType::KeyPress(key)),
+                }
+            } else {
+                // if neither mouse button nor keyboard key, return none
+                None
+            }
+        }
+        EventCode::EV_REL(mouse) => match mouse {
+            EV_REL::REL_X => {
+                let dx = event.value as f64;
+                *x += dx;
+                if let Some((w, _)) = bounds {
+                    if *x < 0.0 {
+                        *x = 0.0;
+                    }
+                    if *x > w {
+                        *x = w;
+                    }
+                }
+                Some(EventType::MouseMove { x: *x, y: *y })
+            }
+            EV_REL::REL_Y => {
+                let dy = event.value as f64;
+                *y += dy;
+                if let Some((_, h)) = bounds {
+                    if *y < 0.0 {
+                        *y = 0.0;
+                    }
+                    if *y > h {
+                        *y = h;
+                    }
+                }
+                Some(EventType::MouseMove { x: *x, y: *y })
+            }
+            EV_REL::REL_HWHEEL => Some(EventType::Wheel {
+                delta_x: event.value.into(),
+                delta_y: 0,
+            }),
+            EV_REL::REL_WHEEL => Some(EventType::Wheel {
+                delta_x: 0,
+                delta_y: event.value.into(),
+            }),
+            // Other EV_REL events cannot be represented by rdev
+            _ => None,
+        },
+        // Other event_codes cannot be represented by rdev,
+        // and some never will e.g. EV_SYN
+        _ => None,
+    }
+}
+
+/// The evdev events for `event`, without the final `SYN_REPORT`, or `None`
+/// when it has no evdev equivalent. `MouseMove` is absolute in rdev and
+/// relative in evdev, so it moves the pointer from `(x, y)`, and
+/// `MouseMoveRelative` moves `(x, y)` along.
+pub fn rdev_event_to_evdev_events(
+    event: &EventType,
+    time: &TimeVal,
+    x: &mut f64,
+    y: &mut f64,
+) -> Option<Vec<InputEvent>> {
+    let key_event =
+        |code: EV_KEY, value| vec![InputEvent::new(time, &EventCode::EV_KEY(code), value)];
+    let events = match event {
+        EventType::KeyPress(key) => key_event(rdev_key_to_evdev(key)?, 1),
+        EventType::KeyRelease(key) => key_event(rdev_key_to_evdev(key)?, 0),
+        EventType::ButtonPress(button) => key_event(rdev_button_to_evdev(button)?, 1),
+        EventType::ButtonRelease(button) => key_event(rdev_button_to_evdev(button)?, 0),
+        EventType::MouseMove { x: to_x, y: to_y } => {
+            relative_motion(time, to_x - *x, to_y - *y, x, y)
+        }
+        EventType::MouseMoveRelative { dx, dy } => relative_motion(time, *dx, *dy, x, y),
+        EventType::Wheel { delta_x, delta_y } => {
+            let mut events = vec![];
+            if *delta_x != 0 {
+                let code = EventCode::EV_REL(EV_REL::REL_HWHEEL);
+                events.push(InputEvent::new(time, &code, *delta_x as i32));
+            }
+            if *delta_y != 0 {
+                let code = EventCode::EV_REL(EV_REL::REL_WHEEL);
+                events.push(InputEvent::new(time, &code, *delta_y as i32));
+            }
+            events
+        }
+    };
+    Some(events)
+}
+
+/// Moves the pointer at `(x, y)` by whole pixels.
+fn relative_motion(time: &TimeVal, dx: f64, dy: f64, x: &mut f64, y: &mut f64) -> Vec<InputEvent> {
+    let (dx, dy) = (dx.round(), dy.round());
+    *x += dx;
+    *y += dy;
+    let mut events = vec![];
+    if dx != 0.0 {
+        events.push(InputEvent::new(
+            time,
+            &EventCode::EV_REL(EV_REL::REL_X),
+            dx as i32,
+        ));
+    }
+    if dy != 0.0 {
+        events.push(InputEvent::new(
+            time,
+            &EventCode::EV_REL(EV_REL::REL_Y),
+            dy as i32,
+        ));
+    }
+    events
+}
+
+/// `Key::Unknown` holds the X11 keycode, which is the evdev one + 8. Only
+/// keyboard keys, the others aren't enabled on our devices.
+fn rdev_key_to_evdev(key: &Key) -> Option<EV_KEY> {
+    match key {
+        Key::Unknown(code) => int_to_ev_key(code.checked_sub(8)?).filter(is_keyboard_key),
+        key => rdev_key_to_evdev_key(key),
+    }
+}
+
+/// `Button::Unknown` holds the X11 button, 8 and 9 are the side buttons.
+fn rdev_button_to_evdev(button: &Button) -> Option<EV_KEY> {
+    match button {
+        Button::Unknown(8) => Some(EV_KEY::BTN_SIDE),
+        Button::Unknown(9) => Some(EV_KEY::BTN_EXTRA),
+        button => rdev_button_to_evdev_key(button),
+    }
+}
+
+/// Keyboard keys, as opposed to the mouse, joystick, gamepad, tablet and
+/// wheel buttons (`BTN_*`) found in the same code range.
+fn is_keyboard_key(key: &EV_KEY) -> bool {
+    let code = key.clone() as u32;
+    let buttons = [
+        EV_KEY::BTN_0 as u32..=EV_KEY::BTN_GEAR_UP as u32,
+        EV_KEY::BTN_DPAD_UP as u32..=EV_KEY::BTN_DPAD_RIGHT as u32,
+        EV_KEY::BTN_TRIGGER_HAPPY1 as u32..=EV_KEY::BTN_TRIGGER_HAPPY40 as u32,
+    ];
+    code < EV_KEY::KEY_MAX as u32 && !buttons.iter().any(|range| range.contains(&code))
+}
+
+/// Enables every keyboard key on `device` (so that `Key::Unknown` codes can
+/// be sent too), the mouse buttons rdev can represent, along with relative
+/// motion and both wheels.
+pub fn enable_rdev_events(device: &Device) -> io::Result<()> {
+    let known_keys = EventCode::EV_KEY(EV_KEY::KEY_RESERVED)
+        .iter()
+        .take_while(|code| matches!(code, EventCode::EV_KEY(_)));
+    for code in known_keys {
+        if let EventCode::EV_KEY(key) = &code {
+            if is_keyboard_key(key)
+                || evdev_key_to_rdev_button(key).is_some()
+                || matches!(key, EV_KEY::BTN_SIDE | EV_KEY::BTN_EXTRA)
+            {
+                device.enable(&code)?;
+            }
+        }
+    }
+    for rel in &[
+        EV_REL::REL_X,
+        EV_REL::REL_Y,
+        EV_REL::REL_WHEEL,
+        EV_REL::REL_HWHEEL,
+    ] {
+        device.enable(&EventCode::EV_REL(rel.clone()))?;
+    }
+    Ok(())
+}
+
+/// A uinput device, along with the keys and buttons it currently reports as
+/// held.
+pub struct OutputDevice {
+    device: UInputDevice,
+    pressed: HashSet<EV_KEY>,
+}
+
+impl OutputDevice {
+    pub fn new(device: UInputDevice) -> OutputDevice {
+        OutputDevice {
+            device,
+            pressed: HashSet::new(),
+        }
+    }
+
+    #[cfg(feature = "unstable_grab")]
+    pub fn devnode(&self) -> Option<&Path> {
+        self.device.devnode().map(Path::new)
+    }
+
+    pub fn write_event(&mut self, event: &InputEvent) -> io::Result<()> {
+        if let EventCode::EV_KEY(key) = &event.event_code {
+            match event.value {
+                0 => {
+                    self.pressed.remove(key);
+                }
+                1 => {
+                    self.pressed.insert(key.clone());
+                }
+                // Auto-repeat
+                _ => (),
+            }
+        }
+        self.device.write_event(event)
+    }
+
+    /// Releases everything that is still held. Once we stop writing to this
+    /// device, the physical releases go to a device the system doesn't
+    /// listen to anymore, and the keys would be stuck.
+    pub fn release_all(&mut self) -> io::Result<()> {
+        if self.pressed.is_empty() {
+            return Ok(());
+        }
+        let time = TimeVal::new(0, 0);
+        for key in self.pressed.drain() {
+            let event = InputEvent::new(&time, &EventCode::EV_KEY(key), 0);
+            self.device.write_event(&event)?;
+        }
+        let sync = InputEvent::new(&time, &EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0);
+        self.device.write_event(&sync)
+    }
+}
+
+impl Drop for OutputDevice {
+    fn drop(&mut self) {
+        self.release_all().ok();
+    }
+}
+
+/// Where `grab` and `listen` get `Event.name` from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeymapSource {
+    /// Use the X server's keymap when a display is reachable, and fall back
+    /// to `Xkb` otherwise.
+    Auto,
+    /// Always use the X server's keymap, `grab` fails without a display.
+    X11,
+    /// Compile the keymap with xkbcommon from the `XKB_DEFAULT_*`
+    /// environment variables. Works under Wayland, in a console or headless.
+    Xkb,
+    /// Don't compute names at all, `Event.name` will always be `None`.
+    Disabled,
+}
+
+/// How `grab` and `listen` turn relative evdev motion into the absolute
+/// `EventType::MouseMove` coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerBounds {
+    /// Ask the X server for the screen size and the current pointer position,
+    /// and behave like `Unbounded` when no display is reachable.
+    Auto,
+    /// Clamp the pointer to a `width`x`height` screen, starting at `(x, y)`.
+    Fixed {
+        width: f64,
+        height: f64,
+        x: f64,
+        y: f64,
+    },
+    /// Don't clamp the pointer, starting at `(0, 0)`.
+    Unbounded,
+}
+
+/// Returns `None` when the keymap can't be loaded, and `Some(None)` when
+/// names are disabled.
+pub fn keyboard_from_source(source: KeymapSource) -> Option<Option<Box<dyn KeyboardState>>> {
+    match source {
+        KeymapSource::Auto => match Keyboard::new() {
+            Some(keyboard) => Some(Some(Box::new(keyboard))),
+            None => keyboard_from_source(KeymapSource::Xkb),
+        },
+        KeymapSource::X11 => {
+            let keyboard = Keyboard::new()?;
+            Some(Some(Box::new(keyboard)))
+        }
+        KeymapSource::Xkb => {
+            let keyboard = XkbKeyboard::new()?;
+            Some(Some(Box::new(keyboard)))
+        }
+        KeymapSource::Disabled => Some(None),
+    }
+}
+
+/// Returns the bounds to clamp the pointer to, and its starting position.
+pub fn pointer_from_bounds(bounds: PointerBounds) -> (Option<(f64, f64)>, f64, f64) {
+    match bounds {
+        PointerBounds::Auto => {
+            let display = match Display::new() {
+                Some(display) => display,
+                None => return (None, 0.0, 0.0),
+            };
+            let size = display.get_size();
+            let (x, y) = display.get_mouse_pos().unwrap_or((0, 0));
+            let bounds = size.map(|(width, height)| (width as f64, height as f64));
+            (bounds, x as f64, y as f64)
+        }
+        PointerBounds::Fixed {
+            width,
+            height,
+            x,
+            y,
+        } => (Some((width, height)), x, y),
+        PointerBounds::Unbounded => (None, 0.0, 0.0),
+    }
+}
+
+pub static DEV_PATH: &str = "/dev/input";
+/// Every uinput device `Simulator` creates has a name starting with this.
+pub static SIMULATED_DEVICE_NAME: &str = "rdev simulated device";
+pub const INOTIFY_DATA: u64 = u64::max_value();
+pub const EPOLLIN: epoll::Events = epoll::Events::EPOLLIN;
+
+/// skip filenames matching "mouse.* or mice".
+/// these files don't play nice with libevdev, not sure why
+/// see: https://askubuntu.com/questions/1043832/difference-between-dev-input-mouse0-and-dev-input-mice
+pub fn is_ignored_file_name(file_name_bytes: &[u8]) -> bool {
+    file_name_bytes == OsStr::new("mice").as_bytes()
+        || file_name_bytes
+            .get(0..=1)
+            .map(|s| s == OsStr::new("js").as_bytes())
+            .unwrap_or(false)
+        || file_name_bytes
+            .get(0..=4)
+            .map(|s| s == OsStr::new("mouse").as_bytes())
+            .unwrap_or(false)
+}
+
+/// Lists the /dev/input device nodes `grab` and `listen` would open.
+pub fn device_paths() -> io::Result<Vec<PathBuf>> {
+    get_device_paths(DEV_PATH)
+}
+
+pub fn get_device_paths<T>(path: T) -> io::Result<Vec<PathBuf>>
+where
+    T: AsRef<Path>,
+{
+    let mut res = Vec::new();
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        // /dev/input files are character devices
+        if !entry.file_type()?.is_char_device() {
+            continue;
+        }
+
+        let path = entry.path();
+        let file_name_bytes = match path.file_name() {
+            Some(file_name) => file_name.as_bytes(),
+            None => continue, // file_name was "..", should be impossible
+        };
+        if is_ignored_file_name(file_name_bytes) {
+            continue;
+        }
+        res.push(path);
+    }
+    Ok(res)
+}
+
+/// Whether `device` is one of the uinput devices of a `Simulator`.
+pub fn is_simulated_device(device: &Device) -> bool {
+    device
+        .name()
+        .is_some_and(|name| name.starts_with(SIMULATED_DEVICE_NAME))
+}
+
+/// Opens the evdev device at `path`, without grabbing it.
+pub fn open_device(path: &Path) -> io::Result<Device> {
+    Device::new_from_fd(File::open(path)?)
+}
+
+pub fn device_fd(device: &Device) -> io::Result<RawFd> {
+    // `fd()` hands out a `File` that would close the fd when dropped
+    device
+        .fd()
+        .map(IntoRawFd::into_raw_fd)
+        .ok_or_else(|| io::Error::from_raw_os_error(libc::EBADF))
+}
+
+pub fn inotify_devices() -> io::Result<Inotify> {
+    let mut inotify = Inotify::init()?;
+    inotify.add_watch(DEV_PATH, WatchMask::CREATE)?;
+    Ok(inotify)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        evdev_event_to_rdev_event, evdev_key_to_rdev_key, is_keyboard_key,
+        rdev_event_to_evdev_events,
+    };
+    use crate::rdev::{EventType, Key};
+    use evdev_rs::{
+        enums::{EventCode, EV_KEY, EV_REL},
+        TimeVal,
+    };
+
+    #[test]
+    fn test_is_keyboard_key() {
+        assert!(is_keyboard_key(&EV_KEY::KEY_A));
+        assert!(is_keyboard_key(&EV_KEY::KEY_OK));
+        assert!(is_keyboard_key(&EV_KEY::KEY_FN_F1));
+        assert!(!is_keyboard_key(&EV_KEY::BTN_LEFT));
+        assert!(!is_keyboard_key(&EV_KEY::BTN_SOUTH));
+        assert!(!is_keyboard_key(&EV_KEY::BTN_DPAD_UP));
+        assert!(!is_keyboard_key(&EV_KEY::BTN_TRIGGER_HAPPY1));
+        assert!(!is_keyboard_key(&EV_KEY::KEY_MAX));
+        // Every key rdev names is a keyboard key
+        let keys = EventCode::EV_KEY(EV_KEY::KEY_RESERVED)
+            .iter()
+            .take_while(|code| matches!(code, EventCode::EV_KEY(_)));
+        for code in keys {
+            if let EventCode::EV_KEY(key) = code {
+                if evdev_key_to_rdev_key(&key).is_some() {
+                    assert!(is_keyboard_key(&key), "{:?}", key);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rdev_to_evdev() {
+        let time = TimeVal::new(0, 0);
+        let (mut x, mut y) = (10.0, 10.0);
+        for event_type in &[
+            EventType::KeyPress(Key::KeyA),
+            EventType::KeyRelease(Key::KpDelete),
+            EventType::KeyPress(Key::Unknown(191)),
+        ] {
+            let events = rdev_event_to_evdev_events(event_type, &time, &mut x, &mut y).unwrap();
+            let back = evdev_event_to_rdev_event(&events[0], &mut x, &mut y, None);
+            match event_type {
+                // grab doesn't convert keys rdev has no name for
+                EventType::KeyPress(Key::Unknown(_)) => assert_eq!(back, None),
+                event_type => assert_eq!(back.as_ref(), Some(event_type)),
+            }
+        }
+
+        // BTN_LEFT and KEY_MAX
+        for code in &[0x110 + 8, 0x2ff + 8] {
+            let event_type = EventType::KeyPress(Key::Unknown(*code));
+            assert!(rdev_event_to_evdev_events(&event_type, &time, &mut x, &mut y).is_none());
+        }
+
+        let move_to = EventType::MouseMove { x: 15.0, y: 10.0 };
+        let events = rdev_event_to_evdev_events(&move_to, &time, &mut x, &mut y).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_code, EventCode::EV_REL(EV_REL::REL_X));
+        assert_eq!(events[0].value, 5);
+        assert_eq!((x, y), (15.0, 10.0));
+
+        let move_by = EventType::MouseMoveRelative { dx: -2.0, dy: 3.4 };
+        let events = rdev_event_to_evdev_events(&move_by, &time, &mut x, &mut y).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_code, EventCode::EV_REL(EV_REL::REL_X));
+        assert_eq!(events[0].value, -2);
+        assert_eq!(events[1].event_code, EventCode::EV_REL(EV_REL::REL_Y));
+        assert_eq!(events[1].value, 3);
+        assert_eq!((x, y), (13.0, 13.0));
+    }
+}
@@ -0,0 +1,307 @@
+use crate::linux::common::is_x11_session;
+use crate::linux::evdev_common::{
+    device_fd, device_paths, evdev_event_to_rdev_event, inotify_devices, is_ignored_file_name,
+    is_simulated_device, keyboard_from_source, open_device, pointer_from_bounds, PointerBounds,
+    DEV_PATH, EPOLLIN, INOTIFY_DATA,
+};
+use crate::linux::listen::{listen_with_options as x11_listen_with_options, ListenOptions};
+use crate::rdev::{Button, Event, EventType, Key, ListenError};
+use epoll::ControlOptions::{EPOLL_CTL_ADD, EPOLL_CTL_DEL};
+use evdev_rs::{
+    enums::{EventCode, EV_KEY, EV_SYN},
+    Device, InputEvent,
+};
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::{
+    ffi::OsStrExt,
+    io::{AsRawFd, RawFd},
+};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Where `listen` gets its events from on Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenBackend {
+    /// `X11` when an X display is reachable and we're not in a Wayland
+    /// session (`WAYLAND_DISPLAY` unset), `Evdev` otherwise.
+    Auto,
+    /// The XRecord extension, needs an X server and only sees the events that
+    /// go through it.
+    X11,
+    /// Reads /dev/input directly, without grabbing anything. Works under
+    /// Wayland, in a console or headless, but needs read access to the
+    /// devices (see the `input` group).
+    Evdev,
+}
+
+pub fn listen<T>(callback: T) -> Result<(), ListenError>
+where
+    T: FnMut(Event) + 'static,
+{
+    listen_with_options(ListenOptions::default(), callback)
+}
+
+pub fn listen_with_options<T>(options: ListenOptions, callback: T) -> Result<(), ListenError>
+where
+    T: FnMut(Event) + 'static,
+{
+    let use_x11 = match options.backend {
+        ListenBackend::Auto => is_x11_session(),
+        ListenBackend::X11 => true,
+        ListenBackend::Evdev => false,
+    };
+    if use_x11 {
+        x11_listen_with_options(options, callback)
+    } else {
+        evdev_listen(&options, callback)
+    }
+}
+
+fn evdev_listen<T>(options: &ListenOptions, mut callback: T) -> Result<(), ListenError>
+where
+    T: FnMut(Event),
+{
+    let mut kb = keyboard_from_source(options.keymap).ok_or(ListenError::KeyboardError)?;
+    let mut pointer = Pointer::new(options.bounds);
+    let mut devices = open_devices()?;
+
+    let epoll_fd = epoll::create(true)?;
+    let result = watch_devices(epoll_fd, &devices).and_then(|()| {
+        let mut inotify = inotify_devices()?;
+        let epoll_event = epoll::Event::new(EPOLLIN, INOTIFY_DATA);
+        epoll::ctl(epoll_fd, EPOLL_CTL_ADD, inotify.as_raw_fd(), epoll_event)?;
+
+        let mut epoll_buffer = [epoll::Event::new(epoll::Events::empty(), 0); 4];
+        let mut inotify_buffer = vec![0_u8; 4096];
+        loop {
+            let num_events = epoll::wait(epoll_fd, -1, &mut epoll_buffer)?;
+            'events: for event in &epoll_buffer[0..num_events] {
+                // new device file created
+                if event.data == INOTIFY_DATA {
+                    for event in inotify.read_events(&mut inotify_buffer)? {
+                        add_device(epoll_fd, event.name, &mut devices)?;
+                    }
+                    continue;
+                }
+                let device = &devices[event.data as usize];
+                let synthetic = is_simulated_device(device);
+                while device.has_event_pending() {
+                    //TODO: deal with EV_SYN::SYN_DROPPED
+                    let (_, event) = match device.next_event(evdev_rs::ReadFlag::NORMAL) {
+                        Ok(event) => event,
+                        Err(_) => {
+                            // Most likely unplugged
+                            let empty_event = epoll::Event::new(epoll::Events::empty(), 0);
+                            epoll::ctl(epoll_fd, EPOLL_CTL_DEL, device_fd(device)?, empty_event)?;
+                            continue 'events;
+                        }
+                    };
+                    let event_type = match pointer.convert(&event) {
+                        Some(event_type) => event_type,
+                        None => continue,
+                    };
+                    // Keep track of simulated modifiers, even if ignored
+                    let name = kb.as_mut().and_then(|kb| kb.add(&event_type));
+                    if synthetic && options.ignore_synthetic {
+                        continue;
+                    }
+                    callback(Event {
+                        time: SystemTime::now(),
+                        name,
+                        event_type,
+                        synthetic,
+                    });
+                }
+            }
+        }
+    });
+    epoll::close(epoll_fd).ok();
+    result.map_err(ListenError::from)
+}
+
+/// Where the pointer is, and whether it moved since the last frame.
+struct Pointer {
+    x: f64,
+    y: f64,
+    bounds: Option<(f64, f64)>,
+    moved: bool,
+}
+
+impl Pointer {
+    fn new(bounds: PointerBounds) -> Pointer {
+        let (bounds, x, y) = pointer_from_bounds(bounds);
+        Pointer {
+            x,
+            y,
+            bounds,
+            moved: false,
+        }
+    }
+
+    /// Like `convert_event`, but with a single `MouseMove` per frame (at its
+    /// `SYN_REPORT`) as X11 does, instead of one for each axis.
+    fn convert(&mut self, event: &InputEvent) -> Option<EventType> {
+        match convert_event(event, &mut self.x, &mut self.y, self.bounds) {
+            Some(EventType::MouseMove { .. }) => {
+                self.moved = true;
+                None
+            }
+            Some(event_type) => Some(event_type),
+            None if self.moved && event.event_code == EventCode::EV_SYN(EV_SYN::SYN_REPORT) => {
+                self.moved = false;
+                Some(EventType::MouseMove {
+                    x: self.x,
+                    y: self.y,
+                })
+            }
+            None => None,
+        }
+    }
+}
+
+/// Same as `evdev_event_to_rdev_event`, except keys and buttons rdev has no
+/// name for come out as `Unknown`, with the code the X11 backend would give.
+fn convert_event(
+    event: &InputEvent,
+    x: &mut f64,
+    y: &mut f64,
+    bounds: Option<(f64, f64)>,
+) -> Option<EventType> {
+    if let Some(event_type) = evdev_event_to_rdev_event(event, x, y, bounds) {
+        return Some(event_type);
+    }
+    let key = match &event.event_code {
+        EventCode::EV_KEY(key) => key,
+        _ => return None,
+    };
+    let pressed = event.value != 0;
+    let event_type = match key {
+        // X11 buttons 8 and 9
+        EV_KEY::BTN_SIDE | EV_KEY::BTN_EXTRA => {
+            let button = match key {
+                EV_KEY::BTN_SIDE => Button::Unknown(8),
+                _ => Button::Unknown(9),
+            };
+            if pressed {
+                EventType::ButtonPress(button)
+            } else {
+                EventType::ButtonRelease(button)
+            }
+        }
+        // X11 keycodes are the evdev ones + 8, and stop at 255
+        key if (key.clone() as u32) < 248 => {
+            let key = Key::Unknown(key.clone() as u32 + 8);
+            if pressed {
+                EventType::KeyPress(key)
+            } else {
+                EventType::KeyRelease(key)
+            }
+        }
+        _ => return None,
+    };
+    Some(event_type)
+}
+
+/// Opens every readable device. Some devices are usually off limits (the
+/// ones not in the `input` group), but if none of them can be opened we would
+/// never hear anything, so that's an error.
+fn open_devices() -> Result<Vec<Device>, ListenError> {
+    let mut devices = Vec::new();
+    let mut last_error = None;
+    for path in device_paths()? {
+        match open_device(&path) {
+            Ok(device) => devices.push(device),
+            Err(error) => last_error = Some((path, error)),
+        }
+    }
+    match last_error {
+        Some((path, error)) if devices.is_empty() => Err(ListenError::DeviceError { path, error }),
+        _ => Ok(devices),
+    }
+}
+
+fn watch_devices(epoll_fd: RawFd, devices: &[Device]) -> io::Result<()> {
+    for (device_idx, device) in devices.iter().enumerate() {
+        let epoll_event = epoll::Event::new(EPOLLIN, device_idx as u64);
+        epoll::ctl(epoll_fd, EPOLL_CTL_ADD, device_fd(device)?, epoll_event)?;
+    }
+    Ok(())
+}
+
+/// Starts listening to a device plugged in while listening, if we can read it.
+fn add_device(
+    epoll_fd: RawFd,
+    file_name: Option<&OsStr>,
+    devices: &mut Vec<Device>,
+) -> io::Result<()> {
+    let file_name = match file_name {
+        Some(file_name) => file_name,
+        None => return Ok(()),
+    };
+    if is_ignored_file_name(file_name.as_bytes()) {
+        return Ok(());
+    }
+    let device = match open_device(&Path::new(DEV_PATH).join(file_name)) {
+        Ok(device) => device,
+        Err(_) => return Ok(()),
+    };
+    let epoll_event = epoll::Event::new(EPOLLIN, devices.len() as u64);
+    epoll::ctl(epoll_fd, EPOLL_CTL_ADD, device_fd(&device)?, epoll_event)?;
+    devices.push(device);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{convert_event, Pointer};
+    use crate::linux::evdev_common::PointerBounds;
+    use crate::linux::keycodes::key_from_code;
+    use crate::rdev::{Button, EventType};
+    use evdev_rs::{
+        enums::{int_to_ev_key, EventCode, EV_KEY, EV_REL, EV_SYN},
+        InputEvent, TimeVal,
+    };
+
+    #[test]
+    fn test_one_move_per_frame() {
+        let mut pointer = Pointer::new(PointerBounds::Unbounded);
+        let time = TimeVal::new(0, 0);
+        let frame = [
+            (EventCode::EV_REL(EV_REL::REL_X), 3),
+            (EventCode::EV_REL(EV_REL::REL_Y), -2),
+            (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0),
+            (EventCode::EV_KEY(EV_KEY::BTN_LEFT), 1),
+            (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0),
+            (EventCode::EV_REL(EV_REL::REL_Y), 5),
+            (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0),
+        ];
+        let events: Vec<EventType> = frame
+            .iter()
+            .filter_map(|(code, value)| pointer.convert(&InputEvent::new(&time, code, *value)))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                EventType::MouseMove { x: 3.0, y: -2.0 },
+                EventType::ButtonPress(Button::Left),
+                EventType::MouseMove { x: 3.0, y: 3.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_keys_as_x11() {
+        let time = TimeVal::new(0, 0);
+        for code in 1..248 {
+            let key = match int_to_ev_key(code) {
+                Some(key) => key,
+                None => continue,
+            };
+            let event = InputEvent::new(&time, &EventCode::EV_KEY(key), 1);
+            let event_type = convert_event(&event, &mut 0.0, &mut 0.0, None);
+            let expected = EventType::KeyPress(key_from_code(code + 8));
+            assert_eq!(event_type, Some(expected), "evdev code {}", code);
+        }
+    }
+}
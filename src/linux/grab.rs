@@ -1,359 +1,302 @@
-use crate::linux::common::Display;
-use crate::linux::keyboard::Keyboard;
-use crate::rdev::{Button, Event, EventType, GrabError, Key, KeyboardState};
+use crate::linux::evdev_common::{
+    device_fd, enable_rdev_events, evdev_event_to_rdev_event, evdev_key_to_rdev_key,
+    get_device_paths, inotify_devices, is_ignored_file_name, is_simulated_device,
+    keyboard_from_source, open_device, pointer_from_bounds, rdev_event_to_evdev_events,
+    KeymapSource, OutputDevice, PointerBounds, DEV_PATH, EPOLLIN, INOTIFY_DATA,
+};
+use crate::rdev::{Event, EventType, GrabError, Key};
 use epoll::ControlOptions::{EPOLL_CTL_ADD, EPOLL_CTL_DEL};
 use evdev_rs::{
-    enums::{EventCode, EV_KEY, EV_REL},
-    Device, InputEvent, UInputDevice,
+    enums::{EventCode, EventType as EvdevType, EV_KEY, EV_SYN},
+    Device, InputEvent, TimeVal, UInputDevice,
 };
-use inotify::{Inotify, WatchMask};
-use std::ffi::{OsStr, OsString};
-use std::fs::{read_dir, File};
-use std::io;
+use inotify::Inotify;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::mem;
 use std::os::unix::{
     ffi::OsStrExt,
-    fs::FileTypeExt,
     io::{AsRawFd, IntoRawFd, RawFd},
 };
-use std::path::Path;
-use std::time::SystemTime;
-
-// TODO The x, y coordinates are currently wrong !! Is there mouse acceleration
-// to take into account ??
-
-macro_rules! convert_keys {
-    ($($ev_key:ident, $rdev_key:ident),*) => {
-        //TODO: make const when rust lang issue #49146 is fixed
-        #[allow(unreachable_patterns)]
-        fn evdev_key_to_rdev_key(key: &EV_KEY) -> Option<Key> {
-            match key {
-                $(
-                    EV_KEY::$ev_key => Some(Key::$rdev_key),
-                )*
-                _ => None,
-            }
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Which uinput devices `grab` writes the events it lets through to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// One uinput clone per grabbed device, keeping each device's name and
+    /// capabilities.
+    PerDevice,
+    /// A single merged `rdev virtual device` keyboard+mouse, with the union of
+    /// the grabbed devices' capabilities and every key and button rdev knows.
+    /// This keeps the device count unchanged for tools that identify devices
+    /// by name, and can carry events the source devices never declared.
+    Merged,
+}
+
+/// A key combination that always stops `grab` and ungrabs every device when
+/// it is held for `hold`, whatever the callback does with those keys. This is
+/// the way out when a buggy callback swallows the whole keyboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscapeChord {
+    pub keys: Vec<Key>,
+    pub hold: Duration,
+}
+
+impl Default for EscapeChord {
+    /// Ctrl+Alt+Shift+Escape (left side keys) held for 2 seconds.
+    fn default() -> EscapeChord {
+        EscapeChord {
+            keys: vec![Key::ControlLeft, Key::Alt, Key::ShiftLeft, Key::Escape],
+            hold: Duration::from_secs(2),
         }
+    }
+}
 
-        // //TODO: make const when rust lang issue #49146 is fixed
-        // fn rdev_key_to_evdev_key(key: &Key) -> Option<EV_KEY> {
-        //     match key {
-        //         $(
-        //             Key::$rdev_key => Some(EV_KEY::$ev_key),
-        //         )*
-        //         _ => None
-        //     }
-        // }
-    };
+/// Options for `grab_with_options`.
+/// `GrabOptions::default()` behaves like `grab`, and only uses the X server
+/// when it is reachable.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct GrabOptions {
+    pub keymap: KeymapSource,
+    pub bounds: PointerBounds,
+    pub output: OutputMode,
+    /// `None` disables the escape chord, which is not recommended.
+    pub escape: Option<EscapeChord>,
+    /// Wait up to this long for every key to be released before grabbing.
+    /// A key held while its device gets grabbed (typically Return, when
+    /// starting from a terminal) never sees its release outside of the grab,
+    /// and auto-repeats forever. Keys still held after that are released
+    /// for the rest of the system, which needs write access to the device.
+    /// `None` grabs right away.
+    pub wait_for_release: Option<Duration>,
+    /// Fail with `GrabError::DeviceError` on the first device that can't be
+    /// grabbed (including devices plugged in while grabbing), instead of
+    /// skipping it and carrying on with the others.
+    pub strict: bool,
 }
 
-macro_rules! convert_buttons {
-    ($($ev_key:ident, $rdev_key:ident),*) => {
-        //TODO: make const when rust lang issue #49146 is fixed
-        fn evdev_key_to_rdev_button(key: &EV_KEY) -> Option<Button> {
-            match key {
-                $(
-                    EV_KEY::$ev_key => Some(Button::$rdev_key),
-                )*
-                _ => None,
-            }
+impl Default for GrabOptions {
+    fn default() -> GrabOptions {
+        GrabOptions {
+            keymap: KeymapSource::Auto,
+            bounds: PointerBounds::Auto,
+            output: OutputMode::PerDevice,
+            escape: Some(EscapeChord::default()),
+            wait_for_release: Some(Duration::from_secs(5)),
+            strict: false,
         }
+    }
+}
 
-        // //TODO: make const when rust lang issue #49146 is fixed
-        // fn rdev_button_to_evdev_key(event: &Button) -> Option<EV_KEY> {
-        //     match event {
-        //         $(
-        //             Button::$rdev_key => Some(EV_KEY::$ev_key),
-        //         )*
-        //         _ => None
-        //     }
-        // }
-    };
+pub fn grab<T>(callback: T) -> Result<(), GrabError>
+where
+    T: Fn(Event) -> Option<Event> + 'static,
+{
+    grab_with_options(GrabOptions::default(), callback)?;
+    Ok(())
 }
 
-#[rustfmt::skip]
-convert_buttons!(
-    BTN_LEFT, Left,
-    BTN_RIGHT, Right,
-    BTN_MIDDLE, Middle
-);
-
-//TODO: IntlBackslash, kpDelete
-#[rustfmt::skip]
-convert_keys!(
-    KEY_ESC, Escape,
-    KEY_1, Num1,
-    KEY_2, Num2,
-    KEY_3, Num3,
-    KEY_4, Num4,
-    KEY_5, Num5,
-    KEY_6, Num6,
-    KEY_7, Num7,
-    KEY_8, Num8,
-    KEY_9, Num9,
-    KEY_0, Num0,
-    KEY_MINUS, Minus,
-    KEY_EQUAL, Equal,
-    KEY_BACKSPACE, Backspace,
-    KEY_TAB, Tab,
-    KEY_Q, KeyQ,
-    KEY_W, KeyW,
-    KEY_E, KeyE,
-    KEY_R, KeyR,
-    KEY_T, KeyT,
-    KEY_Y, KeyY,
-    KEY_U, KeyU,
-    KEY_I, KeyI,
-    KEY_O, KeyO,
-    KEY_P, KeyP,
-    KEY_LEFTBRACE, LeftBracket,
-    KEY_RIGHTBRACE, RightBracket,
-    KEY_ENTER, Return,
-    KEY_LEFTCTRL, ControlLeft,
-    KEY_A, KeyA,
-    KEY_S, KeyS,
-    KEY_D, KeyD,
-    KEY_F, KeyF,
-    KEY_G, KeyG,
-    KEY_H, KeyH,
-    KEY_J, KeyJ,
-    KEY_K, KeyK,
-    KEY_L, KeyL,
-    KEY_SEMICOLON, SemiColon,
-    KEY_APOSTROPHE, Quote,
-    KEY_GRAVE, BackQuote,
-    KEY_LEFTSHIFT, ShiftLeft,
-    KEY_BACKSLASH, BackSlash,
-    KEY_Z, KeyZ,
-    KEY_X, KeyX,
-    KEY_C, KeyC,
-    KEY_V, KeyV,
-    KEY_B, KeyB,
-    KEY_N, KeyN,
-    KEY_M, KeyM,
-    KEY_COMMA, Comma,
-    KEY_DOT, Dot,
-    KEY_SLASH, Slash,
-    KEY_RIGHTSHIFT, ShiftRight,
-    KEY_KPASTERISK , KpMultiply,
-    KEY_LEFTALT, Alt,
-    KEY_SPACE, Space,
-    KEY_CAPSLOCK, CapsLock,
-    KEY_F1, F1,
-    KEY_F2, F2,
-    KEY_F3, F3,
-    KEY_F4, F4,
-    KEY_F5, F5,
-    KEY_F6, F6,
-    KEY_F7, F7,
-    KEY_F8, F8,
-    KEY_F9, F9,
-    KEY_F10, F10,
-    KEY_NUMLOCK, NumLock,
-    KEY_SCROLLLOCK, ScrollLock,
-    KEY_KP7, Kp7,
-    KEY_KP8, Kp8,
-    KEY_KP9, Kp9,
-    KEY_KPMINUS, KpMinus,
-    KEY_KP4, Kp4,
-    KEY_KP5, Kp5,
-    KEY_KP6, Kp6,
-    KEY_KPPLUS, KpPlus,
-    KEY_KP1, Kp1,
-    KEY_KP2, Kp2,
-    KEY_KP3, Kp3,
-    KEY_KP0, Kp0,
-    KEY_F11, F11,
-    KEY_F12, F12,
-    KEY_KPENTER, KpReturn,
-    KEY_RIGHTCTRL, ControlRight,
-    KEY_KPSLASH, KpDivide,
-    KEY_RIGHTALT, AltGr,
-    KEY_HOME , Home,
-    KEY_UP, UpArrow,
-    KEY_PAGEUP, PageUp,
-    KEY_LEFT, LeftArrow,
-    KEY_RIGHT, RightArrow,
-    KEY_END, End,
-    KEY_DOWN, DownArrow,
-    KEY_PAGEDOWN, PageDown,
-    KEY_INSERT, Insert,
-    KEY_DELETE, Delete,
-    KEY_PAUSE, Pause,
-    KEY_LEFTMETA, MetaLeft,
-    KEY_RIGHTMETA, MetaRight,
-    KEY_PRINT, PrintScreen,
-    // KpDelete behaves like normal Delete most of the time
-    KEY_DELETE, KpDelete,
-    // Linux doesn't have an IntlBackslash key
-    KEY_BACKSLASH, IntlBackslash
-);
-
-fn evdev_event_to_rdev_event(
-    event: &InputEvent,
-    x: &mut f64,
-    y: &mut f64,
-    w: f64,
-    h: f64,
-) -> Option<EventType> {
-    match &event.event_code {
-        EventCode::EV_KEY(key) => {
-            if let Some(button) = evdev_key_to_rdev_button(key) {
-                // first check if pressed key is a mouse button
-                match event.value {
-                    0 => Some(EventType::ButtonRelease(button)),
-                    _ => Some(EventType::ButtonPress(button)),
-                }
-            } else if let Some(key) = evdev_key_to_rdev_key(key) {
-                // check if pressed key is a keyboard key
-                match event.value {
-                    0 => Some(EventType::KeyRelease(key)),
-                    _ => Some(EventType::KeyPress(key)),
-                }
+pub fn grab_with_options<T>(options: GrabOptions, callback: T) -> Result<GrabReport, GrabError>
+where
+    T: Fn(Event) -> Option<Event> + 'static,
+{
+    grab_with_context(options, move |event, _| match event {
+        GrabEvent::Input(event) => callback(event),
+        // We never schedule any
+        GrabEvent::Timer(_) => None,
+    })
+}
+
+/// What the `grab_with_context` callback is called with.
+#[derive(Debug)]
+pub enum GrabEvent {
+    /// An input event, returning `None` swallows it, returning `Some` lets it
+    /// through unchanged.
+    Input(Event),
+    /// The token of a timer scheduled with `GrabContext::schedule`, returning
+    /// `Some(event)` sends `event.event_type`.
+    Timer(u64),
+}
+
+pub fn grab_with_context<T>(options: GrabOptions, mut callback: T) -> Result<GrabReport, GrabError>
+where
+    T: FnMut(GrabEvent, &mut GrabContext) -> Option<Event> + 'static,
+{
+    let mut kb = keyboard_from_source(options.keymap).ok_or(GrabError::KeyboardError)?;
+    run(&options, |event, context| match event {
+        LoopEvent::Input(event) => {
+            let pointer = &mut context.pointer;
+            let event_type = match evdev_event_to_rdev_event(
+                &event,
+                &mut pointer.x,
+                &mut pointer.y,
+                pointer.bounds,
+            ) {
+                Some(rdev_event) => rdev_event,
+                // If we can't convert event, simulate it
+                None => return Ok((Some(event), GrabStatus::Continue)),
+            };
+            let name = kb.as_mut().and_then(|kb| kb.add(&event_type));
+            let synthetic = context.source_device().is_some_and(is_simulated_device);
+            let rdev_event = Event {
+                time: SystemTime::now(),
+                name,
+                event_type,
+                synthetic,
+            };
+            if callback(GrabEvent::Input(rdev_event), context).is_some() {
+                Ok((Some(event), GrabStatus::Continue))
             } else {
-                // if neither mouse button nor keyboard key, return none
-                None
+                // callback returns None, swallow the event
+                Ok((None, GrabStatus::Continue))
             }
         }
-        EventCode::EV_REL(mouse) => match mouse {
-            EV_REL::REL_X => {
-                let dx = event.value as f64;
-                *x += dx;
-                if *x < 0.0 {
-                    *x = 0.0;
-                }
-                if *x > w {
-                    *x = w;
+        LoopEvent::Timer(token) => {
+            if let Some(event) = callback(GrabEvent::Timer(token), context) {
+                match context.emit(&event.event_type) {
+                    // Same as returning an input event we can't convert
+                    Err(error) if error.kind() == io::ErrorKind::InvalidInput => (),
+                    result => result?,
                 }
-                Some(EventType::MouseMove { x: *x, y: *y })
             }
-            EV_REL::REL_Y => {
-                let dy = event.value as f64;
-                *y += dy;
-                if *y < 0.0 {
-                    *y = 0.0;
-                }
-                if *y > h {
-                    *y = h;
-                }
-                Some(EventType::MouseMove { x: *x, y: *y })
-            }
-            EV_REL::REL_HWHEEL => Some(EventType::Wheel {
-                delta_x: event.value.into(),
-                delta_y: 0,
-            }),
-            EV_REL::REL_WHEEL => Some(EventType::Wheel {
-                delta_x: 0,
-                delta_y: event.value.into(),
-            }),
-            // Other EV_REL events cannot be represented by rdev
-            _ => None,
-        },
-        // Other event_codes cannot be represented by rdev,
-        // and some never will e.g. EV_SYN
-        _ => None,
-    }
-}
-
-// fn rdev_event_to_evdev_event(event: &EventType, time: &TimeVal) -> Option<InputEvent> {
-//     match event {
-//         EventType::KeyPress(key) => {
-//             let key = rdev_key_to_evdev_key(&key)?;
-//             Some(InputEvent::new(&time, &EventCode::EV_KEY(key), 1))
-//         }
-//         EventType::KeyRelease(key) => {
-//             let key = rdev_key_to_evdev_key(&key)?;
-//             Some(InputEvent::new(&time, &EventCode::EV_KEY(key), 0))
-//         }
-//         EventType::ButtonPress(button) => {
-//             let button = rdev_button_to_evdev_key(&button)?;
-//             Some(InputEvent::new(&time, &EventCode::EV_KEY(button), 1))
-//         }
-//         EventType::ButtonRelease(button) => {
-//             let button = rdev_button_to_evdev_key(&button)?;
-//             Some(InputEvent::new(&time, &EventCode::EV_KEY(button), 0))
-//         }
-//         EventType::MouseMove { x, y } => {
-//             let (x, y) = (*x as i32, *y as i32);
-//             //TODO allow both x and y movements simultaneously
-//             if x != 0 {
-//                 Some(InputEvent::new(&time, &EventCode::EV_REL(EV_REL::REL_X), x))
-//             } else {
-//                 Some(InputEvent::new(&time, &EventCode::EV_REL(EV_REL::REL_Y), y))
-//             }
-//         }
-//         EventType::Wheel { delta_x, delta_y } => {
-//             let (x, y) = (*delta_x as i32, *delta_y as i32);
-//             //TODO allow both x and y movements simultaneously
-//             if x != 0 {
-//                 Some(InputEvent::new(
-//                     &time,
-//                     &EventCode::EV_REL(EV_REL::REL_HWHEEL),
-//                     x,
-//                 ))
-//             } else {
-//                 Some(InputEvent::new(
-//                     &time,
-//                     &EventCode::EV_REL(EV_REL::REL_WHEEL),
-//                     y,
-//                 ))
-//             }
-//         }
-//     }
-// }
+            Ok((None, GrabStatus::Continue))
+        }
+    })
+}
 
-pub fn grab<T>(callback: T) -> Result<(), GrabError>
+/// Grabs every input device and calls `func` with each of their raw events.
+/// The event `func` returns (if any) is written to the corresponding uinput
+/// output device, and `func` can write more events through the `Output`.
+pub fn filter_map_events<F>(options: &GrabOptions, mut func: F) -> Result<GrabReport, GrabError>
 where
-    T: Fn(Event) -> Option<Event> + 'static,
+    F: FnMut(InputEvent, &mut Output) -> (Option<InputEvent>, GrabStatus),
 {
-    let mut kb = Keyboard::new().ok_or(GrabError::KeyboardError)?;
-    let display = Display::new().ok_or(GrabError::MissingDisplayError)?;
-    let (width, height) = display.get_size().ok_or(GrabError::MissingDisplayError)?;
-    let (current_x, current_y) = display
-        .get_mouse_pos()
-        .ok_or(GrabError::MissingDisplayError)?;
-    let mut x = current_x as f64;
-    let mut y = current_y as f64;
-    let w = width as f64;
-    let h = height as f64;
-    filter_map_events(|event| {
-        let event_type = match evdev_event_to_rdev_event(&event, &mut x, &mut y, w, h) {
-            Some(rdev_event) => rdev_event,
-            // If we can't convert event, simulate it
-            None => return (Some(event), GrabStatus::Continue),
-        };
-        let name = kb.add(&event_type);
-        let rdev_event = Event {
-            time: SystemTime::now(),
-            name,
-            event_type,
-        };
-        if callback(rdev_event).is_some() {
-            (Some(event), GrabStatus::Continue)
-        } else {
-            // callback returns None, swallow the event
-            (None, GrabStatus::Continue)
+    run(options, |event, context| match event {
+        LoopEvent::Input(event) => {
+            let mut output = Output {
+                device: context.source_output(),
+            };
+            Ok(func(event, &mut output))
         }
-    })?;
-    Ok(())
+        // We never schedule any
+        LoopEvent::Timer(_) => Ok((None, GrabStatus::Continue)),
+    })
 }
 
-pub fn filter_map_events<F>(mut func: F) -> io::Result<()>
+/// What the grab loop calls its callback with.
+enum LoopEvent {
+    Input(InputEvent),
+    Timer(u64),
+}
+
+/// Everything the grab loop owns while the devices are grabbed.
+struct Grabbed {
+    devices: Vec<Device>,
+    outputs: OutputDevices,
+    timers: Timers,
+    pointer: Pointer,
+}
+
+fn run<F>(options: &GrabOptions, mut func: F) -> Result<GrabReport, GrabError>
 where
-    F: FnMut(InputEvent) -> (Option<InputEvent>, GrabStatus),
+    F: FnMut(LoopEvent, &mut GrabContext) -> io::Result<(Option<InputEvent>, GrabStatus)>,
 {
-    let (epoll_fd, mut devices, output_devices) = setup_devices()?;
-    let mut inotify = setup_inotify(epoll_fd, &devices)?;
+    let (bounds, x, y) = pointer_from_bounds(options.bounds);
+    let mut report = GrabReport::default();
+    let mut last_failure = None;
+    let opened = open_devices(DEV_PATH, options.strict, &mut report, &mut last_failure)?;
+    if let Some(timeout) = options.wait_for_release {
+        wait_for_release(&opened, timeout);
+    }
+    let devices = grab_devices(opened, options.strict, &mut report, &mut last_failure)?;
+    // We would wait forever for devices to show up, typically when the user
+    // isn't in the `input` group
+    if devices.is_empty() {
+        if let Some((path, error)) = last_failure {
+            return Err(GrabError::DeviceError { path, error });
+        }
+    }
+    let outputs =
+        create_output_devices(options.output, &devices).map_err(GrabError::UinputError)?;
+    let mut grabbed = Grabbed {
+        devices,
+        outputs,
+        timers: Timers::new()?,
+        pointer: Pointer { x, y, bounds },
+    };
+    let epoll_fd = epoll_watch_all(&grabbed.devices)?;
+    let escape = options.escape.as_ref().map(EscapeState::new);
 
-    //grab devices
-    devices
-        .iter_mut()
-        .try_for_each(|device| device.grab(evdev_rs::GrabMode::Grab))?;
+    // Whatever happens in the loop, including a panic in `func`, the devices
+    // must be ungrabbed, or the user is left without a keyboard.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        event_loop(
+            epoll_fd,
+            &mut grabbed,
+            options.strict,
+            &mut report,
+            escape,
+            &mut func,
+        )
+    }));
+
+    // Release what we still report as held before the physical devices go
+    // back to the system.
+    grabbed.outputs.release_all();
+    for device in grabbed.devices.iter_mut() {
+        //ungrab devices, ignore errors
+        device.grab(evdev_rs::GrabMode::Ungrab).ok();
+    }
+    // Destroy the uinput devices before a panic propagates
+    drop(grabbed);
+    epoll::close(epoll_fd).ok();
+
+    match result {
+        Ok(result) => result.map(|()| report),
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+fn event_loop<F>(
+    epoll_fd: RawFd,
+    grabbed: &mut Grabbed,
+    strict: bool,
+    report: &mut GrabReport,
+    mut escape: Option<EscapeState>,
+    func: &mut F,
+) -> Result<(), GrabError>
+where
+    F: FnMut(LoopEvent, &mut GrabContext) -> io::Result<(Option<InputEvent>, GrabStatus)>,
+{
+    let Grabbed {
+        devices,
+        outputs,
+        timers,
+        pointer,
+    } = grabbed;
+    let mut inotify = setup_inotify(epoll_fd, devices)?;
+    let epoll_event = epoll::Event::new(EPOLLIN, TIMER_DATA);
+    epoll::ctl(epoll_fd, EPOLL_CTL_ADD, timers.fd, epoll_event)?;
 
     // create buffer for epoll to fill
     let mut epoll_buffer = [epoll::Event::new(epoll::Events::empty(), 0); 4];
     let mut inotify_buffer = vec![0_u8; 4096];
-    'event_loop: loop {
-        let num_events = epoll::wait(epoll_fd, -1, &mut epoll_buffer)?;
+    loop {
+        // The callback may have scheduled or cancelled timers
+        timers.arm()?;
+        // Only wake up on our own if the escape chord is being held
+        let timeout = escape.as_ref().map_or(-1, EscapeState::timeout);
+        let num_events = epoll::wait(epoll_fd, timeout, &mut epoll_buffer)?;
+        if matches!(&escape, Some(escape) if escape.triggered()) {
+            return Ok(());
+        }
 
         //map and simulate events, dealing with
         'events: for event in &epoll_buffer[0..num_events] {
@@ -364,7 +307,23 @@ where
                         event.mask.contains(inotify::EventMask::CREATE),
                         "inotify is listening for events other than file creation"
                     );
-                    add_device_to_epoll_from_inotify_event(epoll_fd, event, &mut devices)?;
+                    add_device_from_inotify_event(
+                        epoll_fd, event, devices, outputs, strict, report,
+                    )?;
+                }
+            } else if event.data == TIMER_DATA {
+                for token in timers.expired() {
+                    let mut context = GrabContext {
+                        devices,
+                        outputs,
+                        timers,
+                        pointer,
+                        source: None,
+                    };
+                    let (_, grab_status) = func(LoopEvent::Timer(token), &mut context)?;
+                    if grab_status == GrabStatus::Stop {
+                        return Ok(());
+                    }
                 }
             } else {
                 // Input device recieved event
@@ -381,141 +340,634 @@ where
                             continue 'events;
                         }
                     };
-                    let (event, grab_status) = func(event);
+                    if let Some(escape) = escape.as_mut() {
+                        escape.update(&event);
+                        if escape.triggered() {
+                            return Ok(());
+                        }
+                    }
+                    let mut context = GrabContext {
+                        devices,
+                        outputs,
+                        timers,
+                        pointer,
+                        source: Some(device_idx),
+                    };
+                    let (event, grab_status) = func(LoopEvent::Input(event), &mut context)?;
 
-                    if let (Some(event), Some(out_device)) = (event, output_devices.get(device_idx))
-                    {
+                    if let (Some(event), Some(out_device)) = (event, outputs.get_mut(device_idx)) {
                         out_device.write_event(&event)?;
                     }
                     if grab_status == GrabStatus::Stop {
-                        break 'event_loop;
+                        return Ok(());
                     }
                 }
             }
         }
     }
+}
+
+/// Handed to the `grab_with_context` callback, to send events of its own and
+/// to act when no input arrives.
+pub struct GrabContext<'a> {
+    devices: &'a [Device],
+    outputs: &'a mut OutputDevices,
+    timers: &'a mut Timers,
+    pointer: &'a mut Pointer,
+    /// The device the current event comes from, `None` for timers.
+    source: Option<usize>,
+}
+
+impl<'a> GrabContext<'a> {
+    /// Calls the callback with `GrabEvent::Timer(token)` once `after` has
+    /// elapsed. Tokens don't need to be unique.
+    pub fn schedule(&mut self, after: Duration, token: u64) {
+        self.timers.schedule(after, token);
+    }
 
-    for device in devices.iter_mut() {
-        //ungrab devices, ignore errors
-        device.grab(evdev_rs::GrabMode::Ungrab).ok();
+    /// Cancels every pending timer scheduled with `token`.
+    pub fn cancel(&mut self, token: u64) {
+        self.timers.cancel(token);
     }
 
-    epoll::close(epoll_fd)?;
-    Ok(())
+    fn source_device(&self) -> Option<&Device> {
+        self.devices.get(self.source?)
+    }
+
+    fn source_output(&mut self) -> Option<&mut OutputDevice> {
+        self.outputs.get_mut(self.source?)
+    }
+
+    /// Sends `event_type` right away, through the same uinput devices as the
+    /// events the grab lets through, so it can't get reordered with them
+    /// (unlike `simulate`, which goes through the X server). Events sent while
+    /// handling an input event come before it if it's let through.
+    /// Fails with `io::ErrorKind::InvalidInput` for events that have no evdev
+    /// equivalent (keys rdev can't map, for instance).
+    pub fn emit(&mut self, event_type: &EventType) -> io::Result<()> {
+        let time = TimeVal::new(0, 0);
+        let pointer = &mut self.pointer;
+        let events =
+            match rdev_event_to_evdev_events(event_type, &time, &mut pointer.x, &mut pointer.y) {
+                Some(events) => events,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "event has no evdev equivalent",
+                    ))
+                }
+            };
+        let first = match events.first() {
+            Some(event) => event,
+            // A move to where the pointer already is
+            None => return Ok(()),
+        };
+        // With one output per device, keys go to a keyboard and motion to a
+        // mouse, preferably the device the current event comes from.
+        let can_carry = |idx: &usize| match self.devices.get(*idx) {
+            Some(device) => device.has(&first.event_code),
+            None => false,
+        };
+        let output_idx = self
+            .source
+            .filter(can_carry)
+            .or_else(|| (0..self.devices.len()).find(can_carry))
+            .or(self.source)
+            .unwrap_or(0);
+        let output = match self.outputs.get_mut(output_idx) {
+            Some(output) => output,
+            None => return Ok(()),
+        };
+        for event in &events {
+            output.write_event(event)?;
+        }
+        output.write_event(&InputEvent::new(
+            &time,
+            &EventCode::EV_SYN(EV_SYN::SYN_REPORT),
+            0,
+        ))
+    }
+}
+
+/// Where `grab` thinks the pointer is.
+struct Pointer {
+    x: f64,
+    y: f64,
+    bounds: Option<(f64, f64)>,
+}
+
+/// Timers scheduled by the callback, backed by a single timerfd armed for the
+/// earliest one.
+struct Timers {
+    fd: RawFd,
+    pending: Vec<(Instant, u64)>,
+    armed: Option<Instant>,
+}
+
+impl Timers {
+    fn new() -> io::Result<Timers> {
+        let flags = libc::TFD_NONBLOCK | libc::TFD_CLOEXEC;
+        let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, flags) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Timers {
+            fd,
+            pending: vec![],
+            armed: None,
+        })
+    }
+
+    fn schedule(&mut self, after: Duration, token: u64) {
+        self.pending.push((Instant::now() + after, token));
+    }
+
+    fn cancel(&mut self, token: u64) {
+        self.pending.retain(|(_, pending)| *pending != token);
+    }
+
+    /// Arms the timerfd for the earliest pending timer, or disarms it.
+    fn arm(&mut self) -> io::Result<()> {
+        let next = self.pending.iter().map(|(deadline, _)| *deadline).min();
+        if next == self.armed {
+            return Ok(());
+        }
+        let after = match next {
+            // A zero it_value disarms the timer, so fire as soon as possible
+            Some(deadline) => deadline
+                .saturating_duration_since(Instant::now())
+                .max(Duration::from_nanos(1)),
+            None => Duration::from_secs(0),
+        };
+        let value = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: after.as_secs() as libc::time_t,
+                tv_nsec: after.subsec_nanos() as libc::c_long,
+            },
+        };
+        if unsafe { libc::timerfd_settime(self.fd, 0, &value, std::ptr::null_mut()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.armed = next;
+        Ok(())
+    }
+
+    /// Takes the tokens of the timers that are due, earliest first.
+    fn expired(&mut self) -> Vec<u64> {
+        // Acknowledge the expiration, nothing to read is fine too
+        let mut expirations = [0_u8; 8];
+        unsafe { libc::read(self.fd, expirations.as_mut_ptr() as *mut libc::c_void, 8) };
+        self.armed = None;
+        let now = Instant::now();
+        let mut expired: Vec<(Instant, u64)> = self
+            .pending
+            .iter()
+            .filter(|(deadline, _)| *deadline <= now)
+            .cloned()
+            .collect();
+        self.pending.retain(|(deadline, _)| *deadline > now);
+        // Stable, so timers due at the same time fire in scheduling order
+        expired.sort_by_key(|(deadline, _)| *deadline);
+        expired.into_iter().map(|(_, token)| token).collect()
+    }
 }
 
-static DEV_PATH: &str = "/dev/input";
-const INOTIFY_DATA: u64 = u64::max_value();
-const EPOLLIN: epoll::Events = epoll::Events::EPOLLIN;
+impl Drop for Timers {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// Blocks until no key is held on any of the `devices`, or `timeout` expired.
+/// The keys still held then are released by writing to their device before
+/// it gets grabbed, so that the system doesn't auto-repeat them.
+fn wait_for_release(devices: &[(PathBuf, Device)], timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    loop {
+        // libevdev only updates its key state when we read events. The devices
+        // aren't grabbed yet, so the rest of the system still gets them.
+        for (_, device) in devices {
+            while device.has_event_pending() {
+                if device.next_event(evdev_rs::ReadFlag::NORMAL).is_err() {
+                    break;
+                }
+            }
+        }
+        if devices
+            .iter()
+            .all(|(_, device)| held_keys(device).is_empty())
+        {
+            return;
+        }
+        if Instant::now() >= deadline {
+            for (path, device) in devices {
+                let held = held_keys(device);
+                if !held.is_empty() {
+                    // Needs write access to the device, the keys stay stuck
+                    // without it
+                    OpenOptions::new()
+                        .write(true)
+                        .open(path)
+                        .and_then(|mut file| write_releases(&mut file, &held))
+                        .ok();
+                }
+            }
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn held_keys(device: &Device) -> Vec<EV_KEY> {
+    EventCode::EV_KEY(EV_KEY::KEY_RESERVED)
+        .iter()
+        .take_while(|code| matches!(code, EventCode::EV_KEY(_)))
+        .filter(|code| device.event_value(code).unwrap_or(0) != 0)
+        .filter_map(|code| match code {
+            EventCode::EV_KEY(key) => Some(key),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Writes a release for each of `keys` and a `SYN_REPORT`, as raw
+/// `input_event`s. Written to a device node, the kernel sends them to every
+/// reader of the device as if they came from it.
+fn write_releases<W: Write>(writer: &mut W, keys: &[EV_KEY]) -> io::Result<()> {
+    let event = |type_: u16, code: u16| libc::input_event {
+        time: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        type_,
+        code,
+        value: 0,
+    };
+    let mut events: Vec<libc::input_event> = keys
+        .iter()
+        .map(|key| event(EvdevType::EV_KEY as u16, key.clone() as u16))
+        .collect();
+    events.push(event(EvdevType::EV_SYN as u16, EV_SYN::SYN_REPORT as u16));
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            events.as_ptr() as *const u8,
+            events.len() * mem::size_of::<libc::input_event>(),
+        )
+    };
+    writer.write_all(bytes)
+}
+
+/// Keeps track of how long the escape chord has been held.
+struct EscapeState<'a> {
+    chord: &'a EscapeChord,
+    pressed: HashSet<Key>,
+    held_since: Option<Instant>,
+}
+
+impl<'a> EscapeState<'a> {
+    fn new(chord: &'a EscapeChord) -> EscapeState<'a> {
+        EscapeState {
+            chord,
+            pressed: HashSet::new(),
+            held_since: None,
+        }
+    }
+
+    fn update(&mut self, event: &InputEvent) {
+        let key = match &event.event_code {
+            EventCode::EV_KEY(key) => match evdev_key_to_rdev_key(key) {
+                Some(key) => key,
+                None => return,
+            },
+            _ => return,
+        };
+        match event.value {
+            0 => {
+                self.pressed.remove(&key);
+            }
+            _ => {
+                self.pressed.insert(key);
+            }
+        }
+        let all_held = !self.chord.keys.is_empty()
+            && self.chord.keys.iter().all(|key| self.pressed.contains(key));
+        if !all_held {
+            self.held_since = None;
+        } else if self.held_since.is_none() {
+            self.held_since = Some(Instant::now());
+        }
+    }
+
+    fn triggered(&self) -> bool {
+        match self.held_since {
+            Some(since) => since.elapsed() >= self.chord.hold,
+            None => false,
+        }
+    }
+
+    /// Milliseconds until the chord triggers, -1 (no timeout) if it isn't held.
+    fn timeout(&self) -> i32 {
+        match self.held_since {
+            Some(since) => {
+                let remaining = self.chord.hold.checked_sub(since.elapsed());
+                // Round up so that we don't wake up just before the deadline
+                remaining.map_or(0, |remaining| {
+                    let millis = remaining.as_micros().div_ceil(1000);
+                    millis.min(i32::MAX as u128) as i32
+                })
+            }
+            None => -1,
+        }
+    }
+}
+
+static MERGED_DEVICE_NAME: &str = "rdev virtual device";
+const TIMER_DATA: u64 = INOTIFY_DATA - 1;
 
 /// Whether to continue grabbing events or to stop
 /// Used in `filter_map_events` (and others)
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub enum GrabStatus {
-    /// Stop grabbing
+    /// Keep grabbing
     Continue,
-    /// ungrab events
+    /// Stop grabbing, and ungrab the devices
     Stop,
 }
 
-fn get_device_files<T>(path: T) -> io::Result<Vec<File>>
+/// The uinput device the event being filtered will be written to, handed to
+/// the `filter_map_events` callback to inject events of its own.
+pub struct Output<'a> {
+    device: Option<&'a mut OutputDevice>,
+}
+
+impl<'a> Output<'a> {
+    /// Writes `event` right away, before the event the callback returns.
+    /// Like with any uinput device, sequences must end with an
+    /// `EV_SYN::SYN_REPORT` for listeners to see them.
+    pub fn write(&mut self, event: &InputEvent) -> io::Result<()> {
+        match self.device.as_mut() {
+            Some(device) => device.write_event(event),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What `grab` did with one of the devices in /dev/input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceStatus {
+    /// The device is grabbed, its events go through the callback.
+    Grabbed,
+    /// Skipped, we aren't allowed to open it (see the `input` group).
+    PermissionDenied,
+    /// Skipped, another program already grabbed it.
+    Busy,
+    /// Skipped, it isn't a device libevdev can handle.
+    Unsupported,
+}
+
+impl DeviceStatus {
+    fn from_error(error: &io::Error) -> DeviceStatus {
+        match error.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EPERM) => DeviceStatus::PermissionDenied,
+            Some(libc::EBUSY) => DeviceStatus::Busy,
+            _ => DeviceStatus::Unsupported,
+        }
+    }
+}
+
+/// The outcome for a single device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceReport {
+    pub path: PathBuf,
+    /// `None` when the device couldn't be opened.
+    pub name: Option<String>,
+    pub status: DeviceStatus,
+}
+
+/// Returned by `grab_with_options` once the grab ends, with the outcome for
+/// every device it tried, including the ones plugged in while grabbing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GrabReport {
+    pub devices: Vec<DeviceReport>,
+}
+
+impl GrabReport {
+    fn push(&mut self, path: PathBuf, name: Option<String>, status: DeviceStatus) {
+        self.devices.push(DeviceReport { path, name, status });
+    }
+}
+
+/// A device we couldn't open or grab, and why.
+type Failure = (PathBuf, io::Error);
+
+/// Records a device we couldn't grab, or fails in strict mode. Gives the
+/// failure back, to report it if nothing could be grabbed at all.
+fn skip_device(
+    path: PathBuf,
+    name: Option<String>,
+    error: io::Error,
+    strict: bool,
+    report: &mut GrabReport,
+) -> Result<Failure, GrabError> {
+    if strict {
+        return Err(GrabError::DeviceError { path, error });
+    }
+    report.push(path.clone(), name, DeviceStatus::from_error(&error));
+    Ok((path, error))
+}
+
+/// Opens every device in `path`, skipping (and reporting) the ones we can't.
+fn open_devices<T>(
+    path: T,
+    strict: bool,
+    report: &mut GrabReport,
+    last_failure: &mut Option<Failure>,
+) -> Result<Vec<(PathBuf, Device)>, GrabError>
 where
     T: AsRef<Path>,
 {
     let mut res = Vec::new();
-    for entry in read_dir(path)? {
-        let entry = entry?;
-        // /dev/input files are character devices
-        if !entry.file_type()?.is_char_device() {
-            continue;
+    for path in get_device_paths(path)? {
+        match open_device(&path) {
+            Ok(device) => res.push((path, device)),
+            Err(error) => *last_failure = Some(skip_device(path, None, error, strict, report)?),
         }
+    }
+    Ok(res)
+}
 
-        let path = entry.path();
-        let file_name_bytes = match path.file_name() {
-            Some(file_name) => file_name.as_bytes(),
-            None => continue, // file_name was "..", should be impossible
-        };
-        // skip filenames matching "mouse.* or mice".
-        // these files don't play nice with libevdev, not sure why
-        // see: https://askubuntu.com/questions/1043832/difference-between-dev-input-mouse0-and-dev-input-mice
-        if file_name_bytes == OsStr::new("mice").as_bytes()
-            || file_name_bytes
-                .get(0..=1)
-                .map(|s| s == OsStr::new("js").as_bytes())
-                .unwrap_or(false)
-            || file_name_bytes
-                .get(0..=4)
-                .map(|s| s == OsStr::new("mouse").as_bytes())
-                .unwrap_or(false)
-        {
-            continue;
+/// Grabs the `opened` devices, skipping (and reporting) the busy ones.
+fn grab_devices(
+    opened: Vec<(PathBuf, Device)>,
+    strict: bool,
+    report: &mut GrabReport,
+    last_failure: &mut Option<Failure>,
+) -> Result<Vec<Device>, GrabError> {
+    let mut devices = Vec::new();
+    for (path, mut device) in opened {
+        let name = device.name().map(String::from);
+        match device.grab(evdev_rs::GrabMode::Grab) {
+            Ok(()) => {
+                report.push(path, name, DeviceStatus::Grabbed);
+                devices.push(device);
+            }
+            // Dropping the device closes it, which releases any grab
+            Err(error) => *last_failure = Some(skip_device(path, name, error, strict, report)?),
         }
-        res.push(File::open(path)?);
     }
-    Ok(res)
+    Ok(devices)
 }
 
-fn epoll_watch_all<'a, T>(device_files: T) -> io::Result<RawFd>
-where
-    T: Iterator<Item = &'a File>,
-{
+fn epoll_watch_all(devices: &[Device]) -> io::Result<RawFd> {
     let epoll_fd = epoll::create(true)?;
     // add file descriptors to epoll
-    for (file_idx, file) in device_files.enumerate() {
-        let epoll_event = epoll::Event::new(EPOLLIN, file_idx as u64);
-        epoll::ctl(epoll_fd, EPOLL_CTL_ADD, file.as_raw_fd(), epoll_event)?;
+    for (device_idx, device) in devices.iter().enumerate() {
+        let epoll_event = epoll::Event::new(EPOLLIN, device_idx as u64);
+        epoll::ctl(epoll_fd, EPOLL_CTL_ADD, device_fd(device)?, epoll_event)?;
     }
     Ok(epoll_fd)
 }
 
-fn inotify_devices() -> io::Result<Inotify> {
-    let mut inotify = Inotify::init()?;
-    inotify.add_watch(DEV_PATH, WatchMask::CREATE)?;
-    Ok(inotify)
-}
-
-fn add_device_to_epoll_from_inotify_event(
+fn add_device_from_inotify_event(
     epoll_fd: RawFd,
     event: inotify::Event<&OsStr>,
     devices: &mut Vec<Device>,
-) -> io::Result<()> {
-    let mut device_path = OsString::from(DEV_PATH);
-    device_path.push(OsString::from("/"));
-    device_path.push(event.name.unwrap());
+    output_devices: &mut OutputDevices,
+    strict: bool,
+    report: &mut GrabReport,
+) -> Result<(), GrabError> {
+    let file_name = match event.name {
+        Some(file_name) => file_name,
+        None => return Ok(()),
+    };
+    let path = Path::new(DEV_PATH).join(file_name);
+    // Our own uinput devices show up here too
+    if is_ignored_file_name(file_name.as_bytes()) || output_devices.contains(&path) {
+        return Ok(());
+    }
     // new plug events
-    let file = File::open(device_path)?;
-    let fd = file.as_raw_fd();
-    let device = Device::new_from_fd(file)?;
+    let mut device = match open_device(&path) {
+        Ok(device) => device,
+        Err(error) => {
+            skip_device(path, None, error, strict, report)?;
+            return Ok(());
+        }
+    };
+    let name = device.name().map(String::from);
+    if let Err(error) = device.grab(evdev_rs::GrabMode::Grab) {
+        skip_device(path, name, error, strict, report)?;
+        return Ok(());
+    }
+    output_devices
+        .add(&device)
+        .map_err(GrabError::UinputError)?;
     let event = epoll::Event::new(EPOLLIN, devices.len() as u64);
+    epoll::ctl(epoll_fd, EPOLL_CTL_ADD, device_fd(&device)?, event)?;
     devices.push(device);
-    epoll::ctl(epoll_fd, EPOLL_CTL_ADD, fd, event)?;
+    report.push(path, name, DeviceStatus::Grabbed);
     Ok(())
 }
 
-/// Returns tuple of epoll_fd, all devices, and uinput devices, where
-/// uinputdevices is the same length as devices, and each uinput device is
-/// a libevdev copy of its corresponding device.The epoll_fd is level-triggered
-/// on any available data in the original devices.
-fn setup_devices() -> io::Result<(RawFd, Vec<Device>, Vec<UInputDevice>)> {
-    let device_files = get_device_files(DEV_PATH)?;
-    let epoll_fd = epoll_watch_all(device_files.iter())?;
-    let devices = device_files
-        .into_iter()
-        .map(Device::new_from_fd)
-        .collect::<io::Result<Vec<Device>>>()?;
-    let output_devices = devices
+/// The uinput devices grabbed events are written to.
+enum OutputDevices {
+    /// Same length as the grabbed devices, each uinput device is a libevdev
+    /// copy of its corresponding device.
+    PerDevice(Vec<OutputDevice>),
+    /// One device for all the grabbed devices.
+    Merged(OutputDevice),
+}
+
+impl OutputDevices {
+    fn get_mut(&mut self, device_idx: usize) -> Option<&mut OutputDevice> {
+        match self {
+            OutputDevices::PerDevice(devices) => devices.get_mut(device_idx),
+            OutputDevices::Merged(device) => Some(device),
+        }
+    }
+
+    /// Adds the output for a device plugged in while grabbing.
+    fn add(&mut self, device: &Device) -> io::Result<()> {
+        if let OutputDevices::PerDevice(devices) = self {
+            devices.push(OutputDevice::new(UInputDevice::create_from_device(device)?));
+        }
+        Ok(())
+    }
+
+    /// Whether `path` is one of our own uinput devices.
+    fn contains(&self, path: &Path) -> bool {
+        let is_path = |device: &OutputDevice| device.devnode() == Some(path);
+        match self {
+            OutputDevices::PerDevice(devices) => devices.iter().any(is_path),
+            OutputDevices::Merged(device) => is_path(device),
+        }
+    }
+
+    fn release_all(&mut self) {
+        match self {
+            OutputDevices::PerDevice(devices) => {
+                for device in devices {
+                    device.release_all().ok();
+                }
+            }
+            OutputDevices::Merged(device) => {
+                device.release_all().ok();
+            }
+        }
+    }
+}
+
+/// Creates a single uinput keyboard+mouse which can emit every event the
+/// `devices` can, plus every key and button rdev can represent.
+pub fn create_merged_device(devices: &[Device]) -> io::Result<UInputDevice> {
+    // libevdev_new only fails when it can't allocate
+    let merged = Device::new().ok_or_else(|| io::Error::from_raw_os_error(libc::ENOMEM))?;
+    merged.set_name(MERGED_DEVICE_NAME);
+    enable_rdev_events(&merged)?;
+    // Union of the capabilities of the grabbed devices.
+    let all_codes = EventCode::EV_KEY(EV_KEY::KEY_RESERVED)
         .iter()
-        .map(UInputDevice::create_from_device)
-        .collect::<io::Result<Vec<UInputDevice>>>()?;
-    Ok((epoll_fd, devices, output_devices))
+        .take_while(|code| !matches!(code, EventCode::EV_SW(_)));
+    for code in all_codes {
+        for device in devices.iter().filter(|device| device.has(&code)) {
+            if let EventCode::EV_ABS(_) = code {
+                // Axes need their range, take it from the first device that has one
+                if let Some(abs_info) = device.abs_info(&code) {
+                    merged.enable_event_code(&code, Some(&abs_info))?;
+                    break;
+                }
+            } else {
+                merged.enable(&code)?;
+                break;
+            }
+        }
+    }
+    UInputDevice::create_from_device(&merged)
+}
+
+fn create_output_devices(output_mode: OutputMode, devices: &[Device]) -> io::Result<OutputDevices> {
+    let output_devices = match output_mode {
+        OutputMode::PerDevice => OutputDevices::PerDevice(
+            devices
+                .iter()
+                .map(|device| UInputDevice::create_from_device(device).map(OutputDevice::new))
+                .collect::<io::Result<Vec<OutputDevice>>>()?,
+        ),
+        OutputMode::Merged => {
+            OutputDevices::Merged(OutputDevice::new(create_merged_device(devices)?))
+        }
+    };
+    Ok(output_devices)
 }
 
 /// Creates an inotify instance looking at /dev/input and adds it to an epoll instance.
 /// Ensures devices isnt too long, which would make the epoll data ambigious.
 fn setup_inotify(epoll_fd: RawFd, devices: &[Device]) -> io::Result<Inotify> {
-    //Ensure there is space for inotify at last epoll index.
-    if devices.len() as u64 >= INOTIFY_DATA {
-        eprintln!("number of devices: {}", devices.len());
+    //Ensure there is space for inotify and the timers at the last epoll indices.
+    if devices.len() as u64 >= TIMER_DATA {
         return Err(io::Error::new(
             io::ErrorKind::Other,
             "too many device files!",
@@ -527,3 +979,48 @@ fn setup_inotify(epoll_fd: RawFd, devices: &[Device]) -> io::Result<Inotify> {
     epoll::ctl(epoll_fd, EPOLL_CTL_ADD, inotify.as_raw_fd(), epoll_event)?;
     Ok(inotify)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{write_releases, Timers};
+    use evdev_rs::enums::EV_KEY;
+    use std::mem;
+    use std::ptr;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_write_releases() {
+        let mut written = vec![];
+        write_releases(&mut written, &[EV_KEY::KEY_ENTER, EV_KEY::KEY_LEFTCTRL]).unwrap();
+        let size = mem::size_of::<libc::input_event>();
+        assert_eq!(written.len(), 3 * size);
+        let events: Vec<(u16, u16, i32)> = written
+            .chunks(size)
+            .map(|bytes| {
+                let event: libc::input_event =
+                    unsafe { ptr::read_unaligned(bytes.as_ptr() as *const _) };
+                (event.type_, event.code, event.value)
+            })
+            .collect();
+        // EV_KEY KEY_ENTER, EV_KEY KEY_LEFTCTRL, EV_SYN SYN_REPORT
+        assert_eq!(events, vec![(1, 28, 0), (1, 29, 0), (0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_timers() {
+        let mut timers = Timers::new().unwrap();
+        timers.schedule(Duration::from_millis(20), 1);
+        timers.schedule(Duration::from_millis(10), 2);
+        timers.schedule(Duration::from_millis(10), 3);
+        timers.schedule(Duration::from_secs(60), 4);
+        timers.schedule(Duration::from_millis(10), 5);
+        timers.cancel(5);
+        timers.arm().unwrap();
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(timers.expired(), vec![2, 3, 1]);
+        assert_eq!(timers.expired(), Vec::<u64>::new());
+        timers.cancel(4);
+        assert!(timers.pending.is_empty());
+    }
+}
@@ -1,18 +1,132 @@
 extern crate x11;
+#[cfg(feature = "xkb")]
+use crate::linux::compose::Compose;
 use crate::linux::keycodes::code_from_key;
+use crate::linux::modifiers::modifier_masks;
 use crate::rdev::{EventType, Key, KeyboardState};
 use std::ffi::CString;
-use std::os::raw::{c_char, c_int, c_uint, c_ulong, c_void};
+use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_ulong, c_ushort, c_void};
 use std::ptr::{null, null_mut, NonNull};
 use x11::xlib;
 
-#[derive(Debug)]
+/// `XkbUseCoreKbd` from XKB.h
+const XKB_USE_CORE_KBD: c_uint = 0x0100;
+/// Where the group goes in a core event state, see `XkbBuildCoreState`.
+const GROUP_SHIFT: c_uint = 13;
+/// XKB never has more than 4 groups.
+const MAX_GROUPS: c_int = 4;
+const XK_MODE_SWITCH: c_ulong = 0xff7e;
+const XK_NUM_LOCK: c_ulong = 0xff7f;
+const XK_CAPS_LOCK: c_ulong = 0xffe5;
+const XK_SHIFT_LOCK: c_ulong = 0xffe6;
+const XK_ISO_NEXT_GROUP: c_ulong = 0xfe08;
+const XK_ISO_PREV_GROUP: c_ulong = 0xfe0a;
+const XK_ISO_FIRST_GROUP: c_ulong = 0xfe0c;
+const XK_ISO_LAST_GROUP: c_ulong = 0xfe0e;
+
+/// `XkbStateRec` from XKBstr.h, the x11 crate has its fields in the wrong
+/// order.
+#[repr(C)]
+#[derive(Default)]
+struct XkbState {
+    group: c_uchar,
+    locked_group: c_uchar,
+    base_group: c_ushort,
+    latched_group: c_ushort,
+    mods: c_uchar,
+    base_mods: c_uchar,
+    latched_mods: c_uchar,
+    locked_mods: c_uchar,
+    compat_state: c_uchar,
+    grab_mods: c_uchar,
+    compat_grab_mods: c_uchar,
+    lookup_mods: c_uchar,
+    compat_lookup_mods: c_uchar,
+    ptr_buttons: c_ushort,
+}
+
+/// A key of the modifier mapping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ModifierKey {
+    keycode: c_uint,
+    mask: c_uint,
+    /// Caps Lock and Num Lock toggle their modifier instead of holding it.
+    locking: bool,
+}
+
+impl ModifierKey {
+    /// The usual mapping, for when the server doesn't give us one.
+    fn defaults() -> Vec<ModifierKey> {
+        [
+            (Key::ShiftLeft, xlib::ShiftMask, false),
+            (Key::ShiftRight, xlib::ShiftMask, false),
+            (Key::CapsLock, xlib::LockMask, true),
+            (Key::ControlLeft, xlib::ControlMask, false),
+            (Key::ControlRight, xlib::ControlMask, false),
+            (Key::Alt, xlib::Mod1Mask, false),
+            (Key::NumLock, xlib::Mod2Mask, true),
+            (Key::MetaLeft, xlib::Mod4Mask, false),
+            (Key::MetaRight, xlib::Mod4Mask, false),
+            (Key::AltGr, xlib::Mod5Mask, false),
+        ]
+        .iter()
+        .filter_map(|(key, mask, locking)| {
+            Some(ModifierKey {
+                keycode: code_from_key(*key)?,
+                mask: *mask,
+                locking: *locking,
+            })
+        })
+        .collect()
+    }
+
+    unsafe fn load(display: *mut xlib::Display) -> Vec<ModifierKey> {
+        let keys: Vec<ModifierKey> = modifier_masks(display)
+            .into_iter()
+            .map(|(keycode, mask)| {
+                let keysym = xlib::XkbKeycodeToKeysym(display, keycode as c_uchar, 0, 0);
+                ModifierKey {
+                    keycode,
+                    mask,
+                    locking: matches!(keysym, XK_CAPS_LOCK | XK_SHIFT_LOCK | XK_NUM_LOCK),
+                }
+            })
+            .collect();
+        if keys.is_empty() {
+            ModifierKey::defaults()
+        } else {
+            keys
+        }
+    }
+}
+
+/// How many groups (layouts) the keymap has.
+unsafe fn group_count(display: *mut xlib::Display) -> c_uint {
+    let mut min_keycode = 0;
+    let mut max_keycode = 0;
+    xlib::XDisplayKeycodes(display, &mut min_keycode, &mut max_keycode);
+    (min_keycode..=max_keycode)
+        .filter_map(|keycode| {
+            (0..MAX_GROUPS)
+                .rev()
+                .find(|group| xlib::XkbKeycodeToKeysym(display, keycode as c_uchar, *group, 0) != 0)
+        })
+        .max()
+        .map_or(1, |group| group as c_uint + 1)
+}
+
+/// Modifiers and group, tracked from the key events.
+#[derive(Debug, Default)]
 struct State {
-    alt: bool,
-    ctrl: bool,
-    caps_lock: bool,
-    shift: bool,
-    meta: bool,
+    /// Modifier keys held down, each one setting its mask.
+    held: Vec<ModifierKey>,
+    /// Modifiers toggled on by Caps Lock, Num Lock...
+    locked: c_uint,
+    /// The locked group, switched by ISO_Next_Group and friends.
+    group: c_uint,
+    /// Keycodes of the Mode_switch keys held, which select the next group.
+    group_shifts: Vec<c_uint>,
+    groups: c_uint,
 }
 
 // Inspired from https://github.com/wavexx/screenkey
@@ -21,34 +135,62 @@ struct State {
 // Other source of inspiration https://gist.github.com/baines/5a49f1334281b2685af5dcae81a6fa8a
 // Needed xproto crate as x11 does not implement _xevent.
 impl State {
-    fn new() -> State {
+    fn new(locked: c_uint, group: c_uint, groups: c_uint) -> State {
+        let groups = groups.max(1);
         State {
-            alt: false,
-            ctrl: false,
-            caps_lock: false,
-            meta: false,
-            shift: false,
+            locked,
+            group: group % groups,
+            groups,
+            ..State::default()
         }
     }
 
+    /// The state field of the key events, with the group in bits 13 and 14.
     fn value(&self) -> c_uint {
-        let mut res: c_uint = 0;
-        if self.alt {
-            res += xlib::Mod1Mask;
-        }
-        if self.ctrl {
-            res += xlib::ControlMask;
-        }
-        if self.caps_lock {
-            res += xlib::LockMask;
+        let mods = self
+            .held
+            .iter()
+            .fold(self.locked, |mods, key| mods | key.mask);
+        let shift = c_uint::from(!self.group_shifts.is_empty());
+        let group = (self.group + shift) % self.groups;
+        mods | (group << GROUP_SHIFT)
+    }
+
+    /// Tracks a key press producing `keysym`, returns whether the key only
+    /// changes the state.
+    fn press(&mut self, keycode: c_uint, keysym: c_ulong, modifiers: &[ModifierKey]) -> bool {
+        let group = match keysym {
+            XK_ISO_NEXT_GROUP => Some((self.group + 1) % self.groups),
+            XK_ISO_PREV_GROUP => Some((self.group + self.groups - 1) % self.groups),
+            XK_ISO_FIRST_GROUP => Some(0),
+            XK_ISO_LAST_GROUP => Some(self.groups - 1),
+            _ => None,
+        };
+        if let Some(group) = group {
+            self.group = group;
+            return true;
         }
-        if self.meta {
-            res += xlib::Mod4Mask;
+        let mut changed = false;
+        if keysym == XK_MODE_SWITCH {
+            if !self.group_shifts.contains(&keycode) {
+                self.group_shifts.push(keycode);
+            }
+            changed = true;
         }
-        if self.shift {
-            res += xlib::ShiftMask;
+        for key in modifiers.iter().filter(|key| key.keycode == keycode) {
+            if key.locking {
+                self.locked ^= key.mask;
+            } else if !self.held.contains(key) {
+                self.held.push(*key);
+            }
+            changed = true;
         }
-        res
+        changed
+    }
+
+    fn release(&mut self, keycode: c_uint) {
+        self.held.retain(|key| key.keycode != keycode);
+        self.group_shifts.retain(|shift| *shift != keycode);
     }
 }
 
@@ -61,7 +203,10 @@ pub struct Keyboard {
     keysym: Box<c_ulong>,
     status: Box<i32>,
     state: State,
+    modifiers: Vec<ModifierKey>,
     serial: c_ulong,
+    #[cfg(feature = "xkb")]
+    compose: Option<Compose>,
 }
 impl Drop for Keyboard {
     fn drop(&mut self) {
@@ -133,16 +278,42 @@ impl Keyboard {
             );
             NonNull::new(xic)?;
             xlib::XSetICFocus(xic);
-            Some(Keyboard {
+            let mut keyboard = Keyboard {
                 xim: Box::new(xim),
                 xic: Box::new(xic),
                 display: Box::new(dpy),
                 window: Box::new(window),
                 keysym: Box::new(0),
                 status: Box::new(0),
-                state: State::new(),
+                state: State::default(),
+                modifiers: ModifierKey::load(dpy),
                 serial: 0,
-            })
+                #[cfg(feature = "xkb")]
+                compose: Compose::from_env(),
+            };
+            keyboard.state = keyboard.initial_state();
+            Some(keyboard)
+        }
+    }
+
+    /// Starts from the locks (Caps Lock, Num Lock) and group the server has
+    /// now, nothing held.
+    unsafe fn initial_state(&self) -> State {
+        let groups = group_count(*self.display);
+        let mut xkb_state = XkbState::default();
+        let status = xlib::XkbGetState(
+            *self.display,
+            XKB_USE_CORE_KBD,
+            &mut xkb_state as *mut XkbState as *mut xlib::XkbStateRec,
+        );
+        if status == 0 {
+            State::new(
+                xkb_state.locked_mods.into(),
+                xkb_state.locked_group.into(),
+                groups,
+            )
+        } else {
+            State::new(0, 0, groups)
         }
     }
 
@@ -155,7 +326,8 @@ impl Keyboard {
             println!("We don't seem to have a display or a xic");
             return None;
         }
-        const BUF_LEN: usize = 4;
+        // Room for what a Compose sequence can give
+        const BUF_LEN: usize = 64;
         let mut buf = [0_u8; BUF_LEN];
         let key = xlib::XKeyEvent {
             display: *self.display,
@@ -193,45 +365,48 @@ impl Keyboard {
             &mut *self.keysym,
             &mut *self.status,
         );
-        if ret == xlib::NoSymbol {
-            return None;
+        let name = if ret == xlib::NoSymbol {
+            None
+        } else {
+            let len = buf.iter().position(|ch| ch == &0).unwrap_or(BUF_LEN);
+            String::from_utf8(buf[..len].to_vec()).ok()
+        };
+        #[cfg(feature = "xkb")]
+        if let Some(compose) = &mut self.compose {
+            return compose.feed(*self.keysym as u32, name);
         }
-
-        let len = buf.iter().position(|ch| ch == &0).unwrap_or(BUF_LEN);
-        String::from_utf8(buf[..len].to_vec()).ok()
+        name
     }
 }
 
 impl KeyboardState for Keyboard {
     fn add(&mut self, event_type: &EventType) -> Option<String> {
         match event_type {
-            EventType::KeyPress(key) => match key {
-                Key::ShiftLeft | Key::ShiftRight => {
-                    self.state.shift = true;
-                    None
-                }
-                Key::CapsLock => {
-                    self.state.caps_lock = !self.state.caps_lock;
-                    None
-                }
-                key => {
-                    let keycode = code_from_key(*key)?;
-                    let state = self.state.value();
-                    unsafe { self.name_from_code(keycode, state) }
-                }
-            },
-            EventType::KeyRelease(key) => match key {
-                Key::ShiftLeft | Key::ShiftRight => {
-                    self.state.shift = false;
+            EventType::KeyPress(key) => {
+                let keycode = code_from_key(*key)?;
+                // Look the key up with the state from *before* it, so that
+                // it doesn't modify itself.
+                let state = self.state.value();
+                let name = unsafe { self.name_from_code(keycode, state) };
+                if self.state.press(keycode, *self.keysym, &self.modifiers) {
                     None
+                } else {
+                    name
                 }
-                _ => None,
-            },
+            }
+            EventType::KeyRelease(key) => {
+                self.state.release(code_from_key(*key)?);
+                None
+            }
             _ => None,
         }
     }
     fn reset(&mut self) {
-        self.state = State::new();
+        self.state = unsafe { self.initial_state() };
+        #[cfg(feature = "xkb")]
+        if let Some(compose) = &mut self.compose {
+            compose.reset();
+        }
     }
 }
 
@@ -266,4 +441,73 @@ mod tests {
             "This test should pass only on Qwerty layout !"
         );
     }
+
+    fn press(state: &mut State, key: Key, keysym: c_ulong) -> bool {
+        let keycode = code_from_key(key).unwrap();
+        state.press(keycode, keysym, &ModifierKey::defaults())
+    }
+
+    fn release(state: &mut State, key: Key) {
+        state.release(code_from_key(key).unwrap());
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let mut state = State::new(0, 0, 1);
+        assert!(press(&mut state, Key::ShiftLeft, 0xffe1));
+        assert!(press(&mut state, Key::ShiftRight, 0xffe2));
+        release(&mut state, Key::ShiftLeft);
+        assert_eq!(state.value(), xlib::ShiftMask);
+        release(&mut state, Key::ShiftRight);
+        assert_eq!(state.value(), 0);
+
+        assert!(press(&mut state, Key::ControlLeft, 0xffe3));
+        assert!(press(&mut state, Key::Alt, 0xffe9));
+        assert!(press(&mut state, Key::AltGr, 0xfe03));
+        assert!(press(&mut state, Key::MetaLeft, 0xffeb));
+        assert_eq!(
+            state.value(),
+            xlib::ControlMask | xlib::Mod1Mask | xlib::Mod5Mask | xlib::Mod4Mask
+        );
+        for key in [Key::ControlLeft, Key::Alt, Key::AltGr, Key::MetaLeft] {
+            release(&mut state, key);
+        }
+        assert_eq!(state.value(), 0);
+        assert!(!press(&mut state, Key::KeyA, 0x61));
+    }
+
+    #[test]
+    fn test_locks() {
+        // Num Lock already on
+        let mut state = State::new(xlib::Mod2Mask, 0, 1);
+        assert!(press(&mut state, Key::CapsLock, 0xffe5));
+        release(&mut state, Key::CapsLock);
+        assert_eq!(state.value(), xlib::LockMask | xlib::Mod2Mask);
+        press(&mut state, Key::NumLock, 0xff7f);
+        release(&mut state, Key::NumLock);
+        press(&mut state, Key::CapsLock, 0xffe5);
+        assert_eq!(state.value(), 0);
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut state = State::new(0, 1, 3);
+        assert_eq!(state.value(), 1 << 13);
+        // Shift+Alt with grp:alt_shift_toggle
+        assert!(press(&mut state, Key::Alt, 0xfe08));
+        assert_eq!(state.value(), 2 << 13);
+        release(&mut state, Key::Alt);
+        press(&mut state, Key::Alt, 0xfe08);
+        assert_eq!(state.value(), 0);
+        press(&mut state, Key::Alt, 0xfe0a);
+        assert_eq!(state.value(), 2 << 13);
+        press(&mut state, Key::Alt, 0xfe0c);
+        assert_eq!(state.value(), 0);
+
+        // Mode_switch selects the next group while held
+        assert!(press(&mut state, Key::AltGr, 0xff7e));
+        assert_eq!(state.value(), xlib::Mod5Mask | 1 << 13);
+        release(&mut state, Key::AltGr);
+        assert_eq!(state.value(), 0);
+    }
 }
@@ -53,6 +53,7 @@ decl_keycodes!(
     Home, 110,
     LeftArrow, 113,
     MetaLeft, 133,
+    MetaRight, 134,
     PageDown, 117,
     PageUp, 112,
     Return, 36,
@@ -1,18 +1,128 @@
 extern crate libc;
 extern crate x11;
 use crate::linux::common::{convert, FALSE, KEYBOARD};
+#[cfg(feature = "evdev")]
+use crate::linux::evdev_common::{KeymapSource, PointerBounds};
+#[cfg(feature = "evdev")]
+use crate::linux::evdev_listen::ListenBackend;
 use crate::linux::keyboard::Keyboard;
 use crate::rdev::{Event, ListenError};
+use std::cell::{Cell, RefCell};
 use std::convert::TryInto;
 use std::ffi::CStr;
 use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_ulong};
 use std::ptr::null;
+use std::slice;
+use x11::xinput;
 use x11::xlib;
 use x11::xrecord;
 
 static mut RECORD_ALL_CLIENTS: c_ulong = xrecord::XRecordAllClients;
 static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event)>> = None;
 
+/// XInput 1 `DeviceKeyPress` to `DeviceMotionNotify`, relative to the
+/// extension's first event.
+const XI_DEVICE_EVENTS: (c_int, c_int) = (1, 5);
+/// The XInput device id is the last byte of its events, the high bit flags
+/// that more events follow.
+const XI_DEVICE_ID_OFFSET: usize = 31;
+
+// The record callback runs on the thread that called `listen`
+thread_local! {
+    /// Range of the XInput device events, when the server has XInput.
+    static XI_EVENTS: Cell<Option<(c_int, c_int)>> = const { Cell::new(None) };
+    static XTEST_DEVICES: RefCell<Vec<u8>> = const { RefCell::new(vec![]) };
+    /// The server records the XInput event of the device an event comes from
+    /// right before the core event, see RecordADeviceEvent in Xorg.
+    static LAST_SOURCE: Cell<Option<u8>> = const { Cell::new(None) };
+}
+
+/// The XInput event range, and the ids of the XTest devices every
+/// `simulate`d event comes from.
+unsafe fn xinput_devices(display: *mut xlib::Display) -> Option<((c_int, c_int), Vec<u8>)> {
+    let name = CStr::from_bytes_with_nul(b"XInputExtension\0").ok()?;
+    let (mut opcode, mut first_event, mut first_error) = (0, 0, 0);
+    let present = xlib::XQueryExtension(
+        display,
+        name.as_ptr(),
+        &mut opcode,
+        &mut first_event,
+        &mut first_error,
+    );
+    if present == 0 {
+        return None;
+    }
+    let mut num_devices = 0;
+    let devices = xinput::XListInputDevices(display, &mut num_devices);
+    if devices.is_null() {
+        return None;
+    }
+    let xtest_devices = slice::from_raw_parts(devices, num_devices.max(0) as usize)
+        .iter()
+        .filter(|info| {
+            !info.name.is_null()
+                && CStr::from_ptr(info.name)
+                    .to_string_lossy()
+                    .contains("XTEST")
+        })
+        .map(|info| info.id as u8)
+        .collect();
+    xinput::XFreeDeviceList(devices);
+    let range = (
+        first_event + XI_DEVICE_EVENTS.0,
+        first_event + XI_DEVICE_EVENTS.1,
+    );
+    Some((range, xtest_devices))
+}
+
+/// Options for `listen_with_options`.
+/// `backend`, `keymap` and `bounds` require the `evdev` feature, `keymap`
+/// and `bounds` only apply to the `Evdev` backend, the X server already
+/// knows both.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ListenOptions {
+    #[cfg(feature = "evdev")]
+    pub backend: ListenBackend,
+    #[cfg(feature = "evdev")]
+    pub keymap: KeymapSource,
+    #[cfg(feature = "evdev")]
+    pub bounds: PointerBounds,
+    /// Don't call the callback for `Event::synthetic` events, to avoid
+    /// feedback loops when simulating from the callback. With `X11` this
+    /// leaves out every XTest event, not only ours.
+    pub ignore_synthetic: bool,
+}
+
+// Only derivable without the `evdev` fields
+#[allow(clippy::derivable_impls)]
+impl Default for ListenOptions {
+    fn default() -> ListenOptions {
+        ListenOptions {
+            #[cfg(feature = "evdev")]
+            backend: ListenBackend::Auto,
+            #[cfg(feature = "evdev")]
+            keymap: KeymapSource::Auto,
+            #[cfg(feature = "evdev")]
+            bounds: PointerBounds::Auto,
+            ignore_synthetic: false,
+        }
+    }
+}
+
+/// Always through XRecord, see `evdev_listen` for the other backend.
+pub fn listen_with_options<T>(options: ListenOptions, mut callback: T) -> Result<(), ListenError>
+where
+    T: FnMut(Event) + 'static,
+{
+    let ignore_synthetic = options.ignore_synthetic;
+    listen(move |event: Event| {
+        if !(ignore_synthetic && event.synthetic) {
+            callback(event)
+        }
+    })
+}
+
 pub fn listen<T>(callback: T) -> Result<(), ListenError>
 where
     T: FnMut(Event) + 'static,
@@ -38,6 +148,16 @@ where
         let mut record_range: xrecord::XRecordRange = *xrecord::XRecordAllocRange();
         record_range.device_events.first = xlib::KeyPress as c_uchar;
         record_range.device_events.last = xlib::MotionNotify as c_uchar;
+        // Plus the XInput events, to know which device core events come from
+        let mut xi_range: xrecord::XRecordRange = *xrecord::XRecordAllocRange();
+        let mut ranges = vec![&mut record_range as *mut xrecord::XRecordRange];
+        if let Some((range, xtest_devices)) = xinput_devices(dpy_control) {
+            xi_range.device_events.first = range.0 as c_uchar;
+            xi_range.device_events.last = range.1 as c_uchar;
+            ranges.push(&mut xi_range);
+            XI_EVENTS.with(|xi_events| xi_events.set(Some(range)));
+            XTEST_DEVICES.with(|devices| *devices.borrow_mut() = xtest_devices);
+        }
 
         // Create context
         let context = xrecord::XRecordCreateContext(
@@ -45,9 +165,8 @@ where
             0,
             &mut RECORD_ALL_CLIENTS,
             1,
-            &mut &mut record_range as *mut &mut xrecord::XRecordRange
-                as *mut *mut xrecord::XRecordRange,
-            1,
+            ranges.as_mut_ptr(),
+            ranges.len() as c_int,
         );
 
         if context == 0 {
@@ -102,10 +221,25 @@ unsafe extern "C" fn record_callback(
     let code: c_uint = xdatum.code.into();
     let type_: c_int = xdatum.type_.into();
 
+    let xi_events = XI_EVENTS.with(Cell::get);
+    if let Some((first, last)) = xi_events {
+        if (first..=last).contains(&type_) {
+            let device_id = *(data.data as *const u8).add(XI_DEVICE_ID_OFFSET) & 0x7f;
+            LAST_SOURCE.with(|source| source.set(Some(device_id)));
+            xrecord::XRecordFreeData(raw_data);
+            return;
+        }
+    }
+    // Only for this core event, the next one may come without its XI event
+    let synthetic = match LAST_SOURCE.with(Cell::take) {
+        Some(device_id) => XTEST_DEVICES.with(|devices| devices.borrow().contains(&device_id)),
+        None => false,
+    };
+
     let x = xdatum.root_x as f64;
     let y = xdatum.root_y as f64;
 
-    if let Some(event) = convert(&mut KEYBOARD, code, type_, x, y) {
+    if let Some(event) = convert(&mut KEYBOARD, code, type_, x, y, synthetic) {
         if let Some(callback) = &mut GLOBAL_CALLBACK {
             callback(event);
         }
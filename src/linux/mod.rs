@@ -1,18 +1,51 @@
+//! Linux specific APIs.
 extern crate libc;
 extern crate x11;
 
 mod common;
+#[cfg(feature = "xkb")]
+mod compose;
 mod display;
 #[cfg(feature = "unstable_grab")]
+pub mod evdev;
+#[cfg(feature = "evdev")]
+mod evdev_common;
+#[cfg(feature = "evdev")]
+mod evdev_listen;
+#[cfg(feature = "unstable_grab")]
 mod grab;
 mod keyboard;
 mod keycodes;
+mod keysyms;
 mod listen;
+mod modifiers;
 mod simulate;
+mod simulator;
+mod text;
+#[cfg(feature = "evdev")]
+mod uinput;
+#[cfg(feature = "xkb")]
+mod xkb;
 
-pub use crate::linux::display::display_size;
+pub(crate) use crate::linux::display::{display_size, mouse_position};
+#[cfg(feature = "evdev")]
+pub use crate::linux::evdev_common::{KeymapSource, PointerBounds};
+#[cfg(feature = "evdev")]
+pub(crate) use crate::linux::evdev_listen::listen;
+#[cfg(feature = "evdev")]
+pub use crate::linux::evdev_listen::{listen_with_options, ListenBackend};
 #[cfg(feature = "unstable_grab")]
-pub use crate::linux::grab::grab;
+pub use crate::linux::grab::{
+    grab, grab_with_context, grab_with_options, DeviceReport, DeviceStatus, EscapeChord,
+    GrabContext, GrabEvent, GrabOptions, GrabReport, OutputMode,
+};
 pub use crate::linux::keyboard::Keyboard;
-pub use crate::linux::listen::listen;
-pub use crate::linux::simulate::simulate;
+#[cfg(not(feature = "evdev"))]
+pub(crate) use crate::linux::listen::listen;
+#[cfg(not(feature = "evdev"))]
+pub use crate::linux::listen::listen_with_options;
+pub use crate::linux::listen::ListenOptions;
+pub(crate) use crate::linux::simulator::{set_clear_modifiers, simulate, simulate_text};
+pub use crate::linux::simulator::{SimulateBackend, Simulator};
+#[cfg(feature = "xkb")]
+pub use crate::linux::xkb::XkbKeyboard;
@@ -0,0 +1,226 @@
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_short, c_uchar, c_uint};
+use std::slice;
+use x11::xinput;
+use x11::xlib;
+
+/// `IsXExtensionKeyboard` from XI.h, what XI2 slave keyboards show up as.
+const IS_X_EXTENSION_KEYBOARD: c_int = 3;
+/// `KeyClass` from XI.h
+const KEY_CLASS: c_uchar = 0;
+/// Lock and Mod2 (NumLock), pressing these again would toggle them.
+const LOCKING_MODIFIERS: [usize; 2] = [1, 4];
+
+/// `XKeyState` from XInput.h, missing from the x11 crate.
+#[repr(C)]
+struct XKeyState {
+    class: c_uchar,
+    length: c_uchar,
+    num_keys: c_short,
+    keys: [c_char; 32],
+}
+
+fn is_pressed(keys: &[c_char; 32], keycode: c_uint) -> bool {
+    let byte = keys[(keycode / 8) as usize] as u8;
+    byte & (1 << (keycode % 8)) != 0
+}
+
+/// Keycodes from a modifier map (8 rows of `max_keypermod` keycodes), except
+/// the locking ones.
+fn modifier_keycodes(max_keypermod: usize, map: &[c_uchar]) -> Vec<c_uint> {
+    let mut keycodes = vec![];
+    for (modifier, row) in map.chunks(max_keypermod).enumerate() {
+        if LOCKING_MODIFIERS.contains(&modifier) {
+            continue;
+        }
+        for keycode in row {
+            let keycode = *keycode as c_uint;
+            if keycode != 0 && !keycodes.contains(&keycode) {
+                keycodes.push(keycode);
+            }
+        }
+    }
+    keycodes
+}
+
+/// Every keycode of a modifier map, with the mask of its modifier.
+fn keycode_masks(max_keypermod: usize, map: &[c_uchar]) -> Vec<(c_uint, c_uint)> {
+    let mut masks = vec![];
+    for (modifier, row) in map.chunks(max_keypermod).enumerate() {
+        for keycode in row {
+            if *keycode != 0 {
+                masks.push((*keycode as c_uint, 1 << modifier));
+            }
+        }
+    }
+    masks
+}
+
+/// The modifier keys of the server's modifier mapping, with the mask each
+/// one sets (`ShiftMask`, `Mod1Mask`...).
+pub unsafe fn modifier_masks(display: *mut xlib::Display) -> Vec<(c_uint, c_uint)> {
+    let map = xlib::XGetModifierMapping(display);
+    if map.is_null() {
+        return vec![];
+    }
+    let max_keypermod = (*map).max_keypermod.max(0) as usize;
+    let masks = if max_keypermod > 0 {
+        let map_keycodes = slice::from_raw_parts((*map).modifiermap, max_keypermod * 8);
+        keycode_masks(max_keypermod, map_keycodes)
+    } else {
+        vec![]
+    };
+    xlib::XFreeModifiermap(map);
+    masks
+}
+
+/// Keycodes of the Shift, Control, Alt, Super... keys currently down, as
+/// the X server sees it (physical keyboards and XTest alike).
+unsafe fn held_modifiers(display: *mut xlib::Display) -> Vec<c_uint> {
+    let map = xlib::XGetModifierMapping(display);
+    if map.is_null() {
+        return vec![];
+    }
+    let max_keypermod = (*map).max_keypermod.max(0) as usize;
+    let keycodes = if max_keypermod > 0 {
+        let map_keycodes = slice::from_raw_parts((*map).modifiermap, max_keypermod * 8);
+        modifier_keycodes(max_keypermod, map_keycodes)
+    } else {
+        vec![]
+    };
+    xlib::XFreeModifiermap(map);
+
+    let mut keys = [0; 32];
+    xlib::XQueryKeymap(display, keys.as_mut_ptr());
+    keycodes
+        .into_iter()
+        .filter(|keycode| is_pressed(&keys, *keycode))
+        .collect()
+}
+
+/// Which of `keycodes` are held on an actual keyboard, leaving out what XTest
+/// pressed. `None` when the XInput extension can't tell.
+pub unsafe fn physically_held(
+    display: *mut xlib::Display,
+    keycodes: &[c_uint],
+) -> Option<Vec<c_uint>> {
+    let mut num_devices = 0;
+    let devices = xinput::XListInputDevices(display, &mut num_devices);
+    if devices.is_null() {
+        return None;
+    }
+    let mut held = vec![];
+    for info in slice::from_raw_parts(devices, num_devices.max(0) as usize) {
+        if info.use_ != IS_X_EXTENSION_KEYBOARD || info.name.is_null() {
+            continue;
+        }
+        // "Virtual core XTEST keyboard", and its equivalents
+        if CStr::from_ptr(info.name)
+            .to_string_lossy()
+            .contains("XTEST")
+        {
+            continue;
+        }
+        let device = xinput::XOpenDevice(display, info.id);
+        if device.is_null() {
+            continue;
+        }
+        let state = xinput::XQueryDeviceState(display, device);
+        if !state.is_null() {
+            let mut class = (*state).data as *const u8;
+            for _ in 0..(*state).num_classes {
+                let header = &*(class as *const xinput::XInputClass);
+                if header.class == KEY_CLASS {
+                    let key_state = &*(class as *const XKeyState);
+                    for keycode in keycodes {
+                        if is_pressed(&key_state.keys, *keycode) && !held.contains(keycode) {
+                            held.push(*keycode);
+                        }
+                    }
+                }
+                if header.length == 0 {
+                    break;
+                }
+                class = class.add(header.length as usize);
+            }
+            xinput::XFreeDeviceState(state);
+        }
+        xinput::XCloseDevice(display, device);
+    }
+    xinput::XFreeDeviceList(devices);
+    Some(held)
+}
+
+/// The `held` modifiers the user is holding on a keyboard, leaving out the
+/// ones we pressed with XTest ourselves, in an earlier `simulate` call for
+/// instance. All of them when XInput can't tell.
+pub unsafe fn user_held_modifiers(display: *mut xlib::Display) -> Vec<c_uint> {
+    let held = held_modifiers(display);
+    let physical = physically_held(display, &held);
+    keep_physical(held, physical)
+}
+
+fn keep_physical(held: Vec<c_uint>, physical: Option<Vec<c_uint>>) -> Vec<c_uint> {
+    match physical {
+        Some(physical) => held
+            .into_iter()
+            .filter(|keycode| physical.contains(keycode))
+            .collect(),
+        None => held,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_pressed, keep_physical, keycode_masks, modifier_keycodes};
+
+    #[test]
+    fn test_modifier_keycodes() {
+        // Shift, Lock, Control, Mod1, Mod2, Mod3, Mod4, Mod5, 2 keys each
+        let map = [
+            50, 62, 66, 0, 37, 105, 64, 108, 77, 0, 0, 0, 133, 134, 92, 0,
+        ];
+        assert_eq!(
+            modifier_keycodes(2, &map),
+            vec![50, 62, 37, 105, 64, 108, 133, 134, 92]
+        );
+    }
+
+    #[test]
+    fn test_keycode_masks() {
+        let map = [50, 62, 66, 0, 37, 105, 64, 0, 77, 0, 0, 0, 133, 0, 92, 0];
+        assert_eq!(
+            keycode_masks(2, &map),
+            vec![
+                (50, 1),
+                (62, 1),
+                (66, 2),
+                (37, 4),
+                (105, 4),
+                (64, 8),
+                (77, 16),
+                (133, 64),
+                (92, 128)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_physical() {
+        // Shift pressed by a previous simulated event, Control by the user
+        let held = vec![50, 37];
+        assert_eq!(keep_physical(held.clone(), Some(vec![37])), vec![37]);
+        assert_eq!(keep_physical(held.clone(), Some(vec![])), vec![]);
+        // Without XInput
+        assert_eq!(keep_physical(held.clone(), None), held);
+    }
+
+    #[test]
+    fn test_is_pressed() {
+        let mut keys = [0; 32];
+        keys[6] = 0b100;
+        assert!(is_pressed(&keys, 50));
+        assert!(!is_pressed(&keys, 49));
+        assert!(!is_pressed(&keys, 255));
+    }
+}
@@ -1,20 +1,24 @@
 use crate::linux::common::{FALSE, TRUE};
 use crate::linux::keycodes::code_from_key;
+use crate::linux::modifiers::{physically_held, user_held_modifiers};
+use crate::linux::text::type_text;
 use crate::rdev::{Button, EventType, SimulateError};
-use std::convert::TryInto;
-use std::os::raw::c_int;
+use std::os::raw::{c_int, c_uint};
 use std::ptr::null;
 use x11::xlib;
 use x11::xtest;
 
-unsafe fn send_native(event_type: &EventType, display: *mut xlib::Display) -> Option<()> {
+unsafe fn send_native(
+    event_type: &EventType,
+    display: *mut xlib::Display,
+) -> Result<(), SimulateError> {
     let res = match event_type {
         EventType::KeyPress(key) => {
-            let code = code_from_key(*key)?;
+            let code = code_from_key(*key).ok_or(SimulateError::UnmappedKey(*key))?;
             xtest::XTestFakeKeyEvent(display, code, TRUE, 0)
         }
         EventType::KeyRelease(key) => {
-            let code = code_from_key(*key)?;
+            let code = code_from_key(*key).ok_or(SimulateError::UnmappedKey(*key))?;
             xtest::XTestFakeKeyEvent(display, code, FALSE, 0)
         }
         EventType::ButtonPress(button) => match button {
@@ -22,7 +26,7 @@ unsafe fn send_native(event_type: &EventType, display: *mut xlib::Display) -> Op
             Button::Middle => xtest::XTestFakeButtonEvent(display, 2, TRUE, 0),
             Button::Right => xtest::XTestFakeButtonEvent(display, 3, TRUE, 0),
             Button::Unknown(code) => {
-                xtest::XTestFakeButtonEvent(display, (*code).try_into().ok()?, TRUE, 0)
+                xtest::XTestFakeButtonEvent(display, c_uint::from(*code), TRUE, 0)
             }
         },
         EventType::ButtonRelease(button) => match button {
@@ -30,28 +34,22 @@ unsafe fn send_native(event_type: &EventType, display: *mut xlib::Display) -> Op
             Button::Middle => xtest::XTestFakeButtonEvent(display, 2, FALSE, 0),
             Button::Right => xtest::XTestFakeButtonEvent(display, 3, FALSE, 0),
             Button::Unknown(code) => {
-                xtest::XTestFakeButtonEvent(display, (*code).try_into().ok()?, FALSE, 0)
+                xtest::XTestFakeButtonEvent(display, c_uint::from(*code), FALSE, 0)
             }
         },
         EventType::MouseMove { x, y } => {
-            //TODO: replace with clamp if it is stabalized
-            let x = if x.is_finite() {
-                x.min(c_int::max_value().into())
-                    .max(c_int::min_value().into())
-                    .round() as c_int
-            } else {
-                0
-            };
-            let y = if y.is_finite() {
-                y.min(c_int::max_value().into())
-                    .max(c_int::min_value().into())
-                    .round() as c_int
-            } else {
-                0
-            };
-            xtest::XTestFakeMotionEvent(display, 0, x, y, 0)
+            xtest::XTestFakeMotionEvent(display, 0, to_c_int(*x), to_c_int(*y), 0)
             //     xlib::XWarpPointer(display, 0, root, 0, 0, 0, 0, *x as i32, *y as i32);
         }
+        EventType::MouseMoveRelative { dx, dy } => {
+            // Not XTestFakeRelativeMotionEvent: the x11 crate binds it with
+            // the screen argument added in libXtst 1.2.4, older libraries
+            // would take our dx for the screen.
+            let (x, y) = pointer_position(display);
+            let (x, y) = (f64::from(x) + dx, f64::from(y) + dy);
+            // -1 keeps the pointer on its current screen
+            xtest::XTestFakeMotionEvent(display, -1, to_c_int(x), to_c_int(y), 0)
+        }
         EventType::Wheel { delta_x, delta_y } => {
             let code_x = if *delta_x > 0 { 7 } else { 6 };
             let code_y = if *delta_y > 0 { 4 } else { 5 };
@@ -70,30 +68,161 @@ unsafe fn send_native(event_type: &EventType, display: *mut xlib::Display) -> Op
             result
         }
     };
+    // XTest requests only fail when the extension is missing
     if res == 0 {
-        None
+        Err(SimulateError::XTestUnavailable)
     } else {
-        Some(())
+        Ok(())
     }
 }
 
-pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
-    unsafe {
-        let dpy = xlib::XOpenDisplay(null());
-        if dpy.is_null() {
-            return Err(SimulateError);
+/// Where the pointer is on its screen. Waits for the server, so the motion
+/// queued before is taken into account.
+unsafe fn pointer_position(display: *mut xlib::Display) -> (c_int, c_int) {
+    let (mut root, mut child) = (0, 0);
+    let (mut root_x, mut root_y, mut x, mut y) = (0, 0, 0, 0);
+    let mut mask = 0;
+    // Only false when the pointer is on another screen, the root
+    // coordinates are still right
+    xlib::XQueryPointer(
+        display,
+        xlib::XDefaultRootWindow(display),
+        &mut root,
+        &mut child,
+        &mut root_x,
+        &mut root_y,
+        &mut x,
+        &mut y,
+        &mut mask,
+    );
+    (root_x, root_y)
+}
+
+/// Rounds a coordinate, clamped to what X11 can take.
+fn to_c_int(value: f64) -> c_int {
+    if value.is_finite() {
+        value.clamp(c_int::MIN.into(), c_int::MAX.into()).round() as c_int
+    } else {
+        0
+    }
+}
+
+/// A connection to the X server, kept open to send events through XTest.
+pub struct X11Simulator {
+    display: *mut xlib::Display,
+}
+
+impl X11Simulator {
+    pub fn new() -> Result<X11Simulator, SimulateError> {
+        let display = unsafe { xlib::XOpenDisplay(null()) };
+        if display.is_null() {
+            return Err(SimulateError::NoDisplay);
+        }
+        // Closes the display if XTest is missing
+        let simulator = X11Simulator { display };
+        let (mut event_base, mut error_base, mut major, mut minor) = (0, 0, 0, 0);
+        let has_xtest = unsafe {
+            xtest::XTestQueryExtension(
+                display,
+                &mut event_base,
+                &mut error_base,
+                &mut major,
+                &mut minor,
+            )
+        };
+        if has_xtest == 0 {
+            return Err(SimulateError::XTestUnavailable);
+        }
+        Ok(simulator)
+    }
+
+    /// Queues `event_type` in Xlib's output buffer, `flush` sends it.
+    pub fn queue(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        unsafe { send_native(event_type, self.display) }
+    }
+
+    /// Sends the output buffer, without waiting for the server.
+    pub fn flush(&mut self) {
+        unsafe {
+            xlib::XFlush(self.display);
+        }
+    }
+
+    /// Types `text`, and waits for the server to have processed it.
+    pub fn type_text(&mut self, text: &str) -> Result<(), SimulateError> {
+        unsafe { type_text(self.display, text) }
+    }
+
+    /// Waits for the server to have processed everything sent so far.
+    pub fn sync(&mut self) {
+        unsafe {
+            xlib::XSync(self.display, FALSE);
         }
-        match send_native(event_type, dpy) {
-            Some(_) => {
-                xlib::XFlush(dpy);
-                xlib::XSync(dpy, 0);
-                xlib::XCloseDisplay(dpy);
-                Ok(())
+    }
+
+    /// Releases the modifiers the user is holding right now, and returns their
+    /// keycodes for `restore_modifiers`. The ones we pressed ourselves stay
+    /// down, a simulated Shift press must still apply to the next key.
+    pub fn release_modifiers(&mut self) -> Vec<c_uint> {
+        unsafe {
+            let held = user_held_modifiers(self.display);
+            for keycode in &held {
+                xtest::XTestFakeKeyEvent(self.display, *keycode, FALSE, 0);
             }
-            None => {
-                xlib::XCloseDisplay(dpy);
-                Err(SimulateError)
+            held
+        }
+    }
+
+    /// Presses the `released` modifiers again, if they are still held on a
+    /// keyboard once everything sent so far is processed. Without XInput, we
+    /// can't tell and press all of them.
+    pub fn restore_modifiers(&mut self, released: &[c_uint]) {
+        if released.is_empty() {
+            return;
+        }
+        self.sync();
+        unsafe {
+            let held = physically_held(self.display, released).unwrap_or_else(|| released.to_vec());
+            for keycode in &held {
+                xtest::XTestFakeKeyEvent(self.display, *keycode, TRUE, 0);
             }
         }
+        self.flush();
+    }
+}
+
+impl Drop for X11Simulator {
+    fn drop(&mut self) {
+        unsafe {
+            // Closing flushes the output buffer
+            xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pointer_position, X11Simulator};
+    use crate::rdev::EventType;
+
+    #[test]
+    fn test_relative_motion() {
+        // Needs an X server
+        let mut simulator = match X11Simulator::new() {
+            Ok(simulator) => simulator,
+            Err(_) => return,
+        };
+        simulator
+            .queue(&EventType::MouseMove { x: 100.0, y: 100.0 })
+            .unwrap();
+        simulator
+            .queue(&EventType::MouseMoveRelative {
+                dx: 15.0,
+                dy: -20.0,
+            })
+            .unwrap();
+        simulator.sync();
+        let position = unsafe { pointer_position(simulator.display) };
+        assert_eq!(position, (115, 80));
     }
 }
@@ -0,0 +1,227 @@
+#[cfg(feature = "evdev")]
+use crate::linux::common::is_x11_session;
+#[cfg(feature = "evdev")]
+use crate::linux::display::display_size;
+use crate::linux::simulate::X11Simulator;
+#[cfg(feature = "evdev")]
+use crate::linux::uinput::UinputSimulator;
+use crate::rdev::{EventType, SimulateError};
+use std::cell::{Cell, RefCell};
+
+/// Where a `Simulator` sends its events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulateBackend {
+    /// `X11` in an X11 session, `Uinput` otherwise (Wayland, console, no
+    /// display at all), falling back to `X11` when the uinput devices can't
+    /// be created but an X server answers. The uinput error is returned when
+    /// both fail. Always `X11` without the `evdev` feature.
+    Auto,
+    /// The XTest extension, needs an X server.
+    X11,
+    /// A virtual keyboard+mouse created through /dev/uinput (requires the
+    /// `evdev` feature). Works without any display server, but needs write
+    /// access to /dev/uinput.
+    Uinput,
+}
+
+/// Sends events through a backend that is set up once (the X server
+/// connection, or the uinput devices), instead of on every call. Much faster
+/// than `simulate` when replaying lots of events, events are sent in order
+/// without having to sleep between them.
+/// Dropping it closes the connection, or destroys the uinput devices after
+/// releasing the keys they still hold.
+///
+/// ```no_run
+/// use rdev::{EventType, Key, SimulateBackend, Simulator};
+///
+/// let mut simulator = Simulator::new(SimulateBackend::Auto).unwrap();
+/// simulator.send(&EventType::KeyPress(Key::KeyS)).unwrap();
+/// simulator.send(&EventType::KeyRelease(Key::KeyS)).unwrap();
+/// simulator
+///     .send_batch(&[
+///         EventType::KeyPress(Key::KeyA),
+///         EventType::KeyRelease(Key::KeyA),
+///     ])
+///     .unwrap();
+/// // Only returns once the X server has processed everything
+/// simulator.flush();
+/// ```
+pub struct Simulator {
+    backend: Backend,
+    clear_modifiers: bool,
+}
+
+enum Backend {
+    X11(X11Simulator),
+    #[cfg(feature = "evdev")]
+    Uinput(UinputSimulator),
+}
+
+impl Simulator {
+    /// `Uinput` sizes its absolute pointer after the X server's screen, and
+    /// can't send `MouseMove` without one, see `with_screen_size`.
+    /// Creating the uinput devices takes a little while, so that the system
+    /// picks them up before the first event is sent.
+    pub fn new(backend: SimulateBackend) -> Result<Simulator, SimulateError> {
+        match backend {
+            #[cfg(feature = "evdev")]
+            SimulateBackend::Auto if !is_x11_session() => {
+                // XWayland (or a stray DISPLAY) still beats nothing when
+                // /dev/uinput is out of reach.
+                Simulator::new(SimulateBackend::Uinput)
+                    .or_else(|err| Simulator::new(SimulateBackend::X11).map_err(|_| err))
+            }
+            SimulateBackend::Auto | SimulateBackend::X11 => {
+                let simulator = X11Simulator::new()?;
+                Ok(Simulator {
+                    backend: Backend::X11(simulator),
+                    clear_modifiers: false,
+                })
+            }
+            #[cfg(feature = "evdev")]
+            SimulateBackend::Uinput => Simulator::with_size(display_size().ok()),
+            // Built without uinput support
+            #[cfg(not(feature = "evdev"))]
+            SimulateBackend::Uinput => Err(SimulateError::UnsupportedEvent),
+        }
+    }
+
+    /// A `Uinput` simulator for a `width`x`height` screen (requires the
+    /// `evdev` feature).
+    #[cfg(feature = "evdev")]
+    pub fn with_screen_size(width: u64, height: u64) -> Result<Simulator, SimulateError> {
+        Simulator::with_size(Some((width, height)))
+    }
+
+    #[cfg(feature = "evdev")]
+    fn with_size(screen_size: Option<(u64, u64)>) -> Result<Simulator, SimulateError> {
+        let simulator = UinputSimulator::new(screen_size)?;
+        Ok(Simulator {
+            backend: Backend::Uinput(simulator),
+            clear_modifiers: false,
+        })
+    }
+
+    /// Off by default. When on, modifiers the user is holding (Shift,
+    /// Control, Alt, Super, AltGr) are released before sending anything, so
+    /// that a hotkey handler doesn't type Ctrl+text, and pressed again
+    /// afterwards if they are still held. Modifiers pressed by simulated
+    /// events stay down, unless the X server lacks XInput to tell them apart.
+    /// Each send then waits for the events to be processed. Only for `X11`,
+    /// uinput can't release keys held on other devices: `Uinput` sends fail
+    /// with `UnsupportedEvent` while this is on.
+    pub fn set_clear_modifiers(&mut self, clear: bool) {
+        self.clear_modifiers = clear;
+    }
+
+    /// Sends `event_type` right away, without waiting for it to be processed.
+    pub fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        let clear_modifiers = self.clear_modifiers;
+        match &mut self.backend {
+            Backend::X11(simulator) => with_modifiers_cleared(simulator, clear_modifiers, |s| {
+                s.queue(event_type)?;
+                s.flush();
+                Ok(())
+            }),
+            #[cfg(feature = "evdev")]
+            Backend::Uinput(_) if clear_modifiers => Err(SimulateError::UnsupportedEvent),
+            #[cfg(feature = "evdev")]
+            Backend::Uinput(simulator) => simulator.send(event_type),
+        }
+    }
+
+    /// Sends all of `events` at once. Stops at the first one that can't be
+    /// sent, the ones before it are still sent.
+    pub fn send_batch(&mut self, events: &[EventType]) -> Result<(), SimulateError> {
+        let clear_modifiers = self.clear_modifiers;
+        match &mut self.backend {
+            Backend::X11(simulator) => with_modifiers_cleared(simulator, clear_modifiers, |s| {
+                let result = events.iter().try_for_each(|event| s.queue(event));
+                s.flush();
+                result
+            }),
+            #[cfg(feature = "evdev")]
+            Backend::Uinput(_) if clear_modifiers => Err(SimulateError::UnsupportedEvent),
+            #[cfg(feature = "evdev")]
+            Backend::Uinput(simulator) => events.iter().try_for_each(|event| simulator.send(event)),
+        }
+    }
+
+    /// Types `text` as if it came from the keyboard, whatever the layout.
+    /// Characters missing from the keymap are typed by borrowing an unused
+    /// keycode for a moment. Only the `X11` backend can type text, the keymap
+    /// of a Wayland compositor is out of reach.
+    pub fn send_text(&mut self, text: &str) -> Result<(), SimulateError> {
+        let clear_modifiers = self.clear_modifiers;
+        match &mut self.backend {
+            Backend::X11(simulator) => {
+                with_modifiers_cleared(simulator, clear_modifiers, |s| s.type_text(text))
+            }
+            #[cfg(feature = "evdev")]
+            Backend::Uinput(_) => Err(SimulateError::UnsupportedEvent),
+        }
+    }
+
+    /// Waits until everything sent so far has been processed. The kernel
+    /// processes uinput events as they are written, so this only matters for
+    /// X11.
+    pub fn flush(&mut self) {
+        match &mut self.backend {
+            Backend::X11(simulator) => simulator.sync(),
+            #[cfg(feature = "evdev")]
+            Backend::Uinput(_) => (),
+        }
+    }
+}
+
+fn with_modifiers_cleared<T>(
+    simulator: &mut X11Simulator,
+    clear: bool,
+    send: impl FnOnce(&mut X11Simulator) -> T,
+) -> T {
+    if !clear {
+        return send(simulator);
+    }
+    let released = simulator.release_modifiers();
+    let result = send(simulator);
+    simulator.restore_modifiers(&released);
+    result
+}
+
+thread_local! {
+    static SIMULATOR: RefCell<Option<Simulator>> = const { RefCell::new(None) };
+    static CLEAR_MODIFIERS: Cell<bool> = const { Cell::new(false) };
+}
+
+fn with_simulator<T>(
+    f: impl FnOnce(&mut Simulator) -> Result<T, SimulateError>,
+) -> Result<T, SimulateError> {
+    SIMULATOR.with(|simulator| {
+        let mut simulator = simulator.borrow_mut();
+        let simulator = match simulator.as_mut() {
+            Some(simulator) => simulator,
+            None => simulator.insert(Simulator::new(SimulateBackend::Auto)?),
+        };
+        simulator.set_clear_modifiers(CLEAR_MODIFIERS.with(Cell::get));
+        f(simulator)
+    })
+}
+
+/// Each thread keeps its own `SimulateBackend::Auto` simulator around, opening
+/// a connection (or worse, creating uinput devices) on every call is slow.
+pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
+    with_simulator(|simulator| {
+        simulator.send(event_type)?;
+        // Like it always did, only return once the event is processed
+        simulator.flush();
+        Ok(())
+    })
+}
+
+pub fn set_clear_modifiers(clear: bool) {
+    CLEAR_MODIFIERS.with(|clear_modifiers| clear_modifiers.set(clear));
+}
+
+pub fn simulate_text(text: &str) -> Result<(), SimulateError> {
+    with_simulator(|simulator| simulator.send_text(text))
+}
@@ -0,0 +1,331 @@
+use crate::linux::common::{FALSE, TRUE};
+use crate::linux::keycodes::code_from_key;
+use crate::linux::keysyms::{keysym_to_char, XK_RETURN, XK_TAB};
+use crate::rdev::{Key, SimulateError};
+use std::os::raw::{c_int, c_uchar, c_uint, c_ulong};
+use std::slice;
+use std::thread;
+use std::time::Duration;
+use x11::xlib;
+use x11::xtest;
+
+type KeySym = c_ulong;
+
+const NO_SYMBOL: KeySym = 0;
+const XK_ISO_LEVEL3_SHIFT: KeySym = 0xfe03;
+/// Clients look keysyms up when they handle the key events, give them some
+/// time to do so before taking a remapped keycode back.
+const REMAP_DELAY: Duration = Duration::from_millis(50);
+
+/// The keysym for a character, see Appendix A of the X protocol
+/// ("KEYSYM Encoding"): Latin-1 keysyms are their code point, and every
+/// other character has a Unicode keysym. Only for remapping, keymaps may
+/// use a legacy keysym for the same character.
+pub fn keysym_from_char(c: char) -> KeySym {
+    match c {
+        '\n' => XK_RETURN,
+        '\t' => XK_TAB,
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as KeySym,
+        c => 0x0100_0000 | c as KeySym,
+    }
+}
+
+/// Which modifiers give a keysym from a keycode, in the order we try them.
+/// Columns 2 and 3 of the core keyboard mapping are the second group, which
+/// we can't select, 4 and 5 are the third and fourth levels of the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Base,
+    Shift,
+    AltGr,
+    AltGrShift,
+}
+
+impl Level {
+    const ALL: [(Level, usize); 4] = [
+        (Level::Base, 0),
+        (Level::Shift, 1),
+        (Level::AltGr, 4),
+        (Level::AltGrShift, 5),
+    ];
+
+    fn shift(self) -> bool {
+        matches!(self, Level::Shift | Level::AltGrShift)
+    }
+
+    fn altgr(self) -> bool {
+        matches!(self, Level::AltGr | Level::AltGrShift)
+    }
+}
+
+/// A copy of the server's core keyboard mapping.
+#[derive(Debug)]
+pub struct KeyboardMapping {
+    min_keycode: c_uint,
+    keysyms_per_keycode: usize,
+    keysyms: Vec<KeySym>,
+}
+
+impl KeyboardMapping {
+    unsafe fn load(display: *mut xlib::Display) -> Option<KeyboardMapping> {
+        let mut min_keycode = 0;
+        let mut max_keycode = 0;
+        xlib::XDisplayKeycodes(display, &mut min_keycode, &mut max_keycode);
+        let count = max_keycode - min_keycode + 1;
+        let mut keysyms_per_keycode = 0;
+        let keysyms = xlib::XGetKeyboardMapping(
+            display,
+            min_keycode as c_uchar,
+            count,
+            &mut keysyms_per_keycode,
+        );
+        if keysyms.is_null() {
+            return None;
+        }
+        let len = (count * keysyms_per_keycode) as usize;
+        let mapping = KeyboardMapping {
+            min_keycode: min_keycode as c_uint,
+            keysyms_per_keycode: keysyms_per_keycode as usize,
+            keysyms: slice::from_raw_parts(keysyms, len).to_vec(),
+        };
+        xlib::XFree(keysyms as *mut _);
+        Some(mapping)
+    }
+
+    fn keycodes(&self) -> impl Iterator<Item = (c_uint, &[KeySym])> {
+        let min_keycode = self.min_keycode;
+        self.keysyms
+            .chunks(self.keysyms_per_keycode)
+            .enumerate()
+            .map(move |(idx, keysyms)| (min_keycode + idx as c_uint, keysyms))
+    }
+
+    /// The keycode and level typing `c`, preferring the lowest level. AltGr
+    /// levels are only considered when `altgr` is set. With `caps_lock`,
+    /// letters are found at the level that gives their case once locked.
+    pub fn find(&self, c: char, altgr: bool, caps_lock: bool) -> Option<(c_uint, Level)> {
+        Level::ALL
+            .iter()
+            .filter(|(level, _)| altgr || !level.altgr())
+            .find_map(|(level, column)| {
+                self.keycodes()
+                    .find(|(_, keysyms)| typed_char(keysyms, *column, caps_lock) == Some(c))
+                    .map(|(keycode, _)| (keycode, *level))
+            })
+    }
+
+    /// Keycodes without any keysym, that we can borrow.
+    pub fn spare_keycodes(&self) -> Vec<c_uint> {
+        self.keycodes()
+            .filter(|(_, keysyms)| keysyms.iter().all(|keysym| *keysym == NO_SYMBOL))
+            .map(|(keycode, _)| keycode)
+            .collect()
+    }
+
+    fn original(&self, keycode: c_uint) -> &[KeySym] {
+        let start = (keycode - self.min_keycode) as usize * self.keysyms_per_keycode;
+        &self.keysyms[start..start + self.keysyms_per_keycode]
+    }
+}
+
+/// The character typed at `column` of a keycode. Caps Lock swaps the levels
+/// of a lowercase letter and its uppercase, like XKB's alphabetic key types.
+fn typed_char(keysyms: &[KeySym], column: usize, caps_lock: bool) -> Option<char> {
+    let typed = keysym_to_char(*keysyms.get(column)?);
+    if !caps_lock {
+        return typed;
+    }
+    // Base and Shift, or AltGr and AltGr+Shift
+    let other = keysyms.get(column ^ 1).copied().and_then(keysym_to_char);
+    let (lower, upper) = if column & 1 == 0 {
+        (typed, other)
+    } else {
+        (other, typed)
+    };
+    match (lower, upper) {
+        (Some(lower), Some(upper))
+            if lower.is_lowercase() && lower.to_uppercase().eq(Some(upper)) =>
+        {
+            other
+        }
+        _ => typed,
+    }
+}
+
+/// Whether Caps Lock (the Lock modifier) is locked.
+unsafe fn caps_lock(display: *mut xlib::Display) -> bool {
+    let (mut root, mut child) = (0, 0);
+    let (mut root_x, mut root_y, mut x, mut y) = (0, 0, 0, 0);
+    let mut mask = 0;
+    xlib::XQueryPointer(
+        display,
+        xlib::XDefaultRootWindow(display),
+        &mut root,
+        &mut child,
+        &mut root_x,
+        &mut root_y,
+        &mut x,
+        &mut y,
+        &mut mask,
+    );
+    mask & xlib::LockMask != 0
+}
+
+/// Types `text` with XTest. Characters the keymap can't produce are typed by
+/// temporarily mapping their keysym to a spare keycode, like xdotool does,
+/// and the keymap is restored before returning.
+pub unsafe fn type_text(display: *mut xlib::Display, text: &str) -> Result<(), SimulateError> {
+    let mapping = KeyboardMapping::load(display).ok_or(SimulateError::UnsupportedEvent)?;
+    let shift = code_from_key(Key::ShiftLeft).ok_or(SimulateError::UnmappedKey(Key::ShiftLeft))?;
+    let altgr = xlib::XKeysymToKeycode(display, XK_ISO_LEVEL3_SHIFT) as c_uint;
+    let caps_lock = caps_lock(display);
+    let mut spares = mapping.spare_keycodes();
+    // Borrowed keycodes and the keysym we gave them
+    let mut remapped: Vec<(c_uint, KeySym)> = vec![];
+
+    let mut result = Ok(());
+    for c in text.chars() {
+        let keysym = keysym_from_char(c);
+        let (keycode, level) = match mapping.find(c, altgr != 0, caps_lock) {
+            Some(found) => found,
+            None => match remapped.iter().find(|(_, remapped)| *remapped == keysym) {
+                Some((keycode, _)) => (*keycode, Level::Base),
+                None => {
+                    if spares.is_empty() {
+                        // Everything is borrowed, take it all back first
+                        restore(display, &mapping, &mut remapped);
+                        spares = mapping.spare_keycodes();
+                    }
+                    let keycode = match spares.pop() {
+                        Some(keycode) => keycode,
+                        None => {
+                            // No spare keycode at all
+                            result = Err(SimulateError::UnsupportedEvent);
+                            break;
+                        }
+                    };
+                    remap(display, &mapping, keycode, keysym);
+                    remapped.push((keycode, keysym));
+                    (keycode, Level::Base)
+                }
+            },
+        };
+        let mut modifiers = vec![];
+        if level.shift() {
+            modifiers.push(shift);
+        }
+        if level.altgr() {
+            modifiers.push(altgr);
+        }
+        for modifier in &modifiers {
+            xtest::XTestFakeKeyEvent(display, *modifier, TRUE, 0);
+        }
+        xtest::XTestFakeKeyEvent(display, keycode, TRUE, 0);
+        xtest::XTestFakeKeyEvent(display, keycode, FALSE, 0);
+        for modifier in modifiers.iter().rev() {
+            xtest::XTestFakeKeyEvent(display, *modifier, FALSE, 0);
+        }
+    }
+    restore(display, &mapping, &mut remapped);
+    xlib::XSync(display, FALSE);
+    result
+}
+
+unsafe fn remap(
+    display: *mut xlib::Display,
+    mapping: &KeyboardMapping,
+    keycode: c_uint,
+    keysym: KeySym,
+) {
+    // Same keysym with and without Shift, so that a held Shift doesn't matter
+    let mut keysyms = vec![NO_SYMBOL; mapping.keysyms_per_keycode];
+    for slot in keysyms.iter_mut().take(2) {
+        *slot = keysym;
+    }
+    change_mapping(display, mapping, keycode, &mut keysyms);
+}
+
+/// Gives the `remapped` keycodes their original (empty) keysyms back.
+unsafe fn restore(
+    display: *mut xlib::Display,
+    mapping: &KeyboardMapping,
+    remapped: &mut Vec<(c_uint, KeySym)>,
+) {
+    if remapped.is_empty() {
+        return;
+    }
+    xlib::XSync(display, FALSE);
+    thread::sleep(REMAP_DELAY);
+    for (keycode, _) in remapped.drain(..) {
+        let mut keysyms = mapping.original(keycode).to_vec();
+        change_mapping(display, mapping, keycode, &mut keysyms);
+    }
+}
+
+unsafe fn change_mapping(
+    display: *mut xlib::Display,
+    mapping: &KeyboardMapping,
+    keycode: c_uint,
+    keysyms: &mut [KeySym],
+) {
+    xlib::XChangeKeyboardMapping(
+        display,
+        keycode as c_int,
+        mapping.keysyms_per_keycode as c_int,
+        keysyms.as_mut_ptr(),
+        1,
+    );
+    // Clients must get the MappingNotify before the key events
+    xlib::XSync(display, FALSE);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{keysym_from_char, KeyboardMapping, Level, NO_SYMBOL};
+
+    #[test]
+    fn test_keysym_from_char() {
+        assert_eq!(keysym_from_char('a'), 0x61);
+        assert_eq!(keysym_from_char('ü'), 0xfc);
+        assert_eq!(keysym_from_char('ß'), 0xdf);
+        assert_eq!(keysym_from_char('€'), 0x0100_20ac);
+        assert_eq!(keysym_from_char('🙂'), 0x0101_f642);
+        assert_eq!(keysym_from_char('\n'), 0xff0d);
+    }
+
+    #[test]
+    fn test_find() {
+        // keycode 8: a A, keycode 9: e E with € (EuroSign) on AltGr,
+        // keycode 10: nothing, keycode 11: Cyrillic я Я, keycode 12: 1 !
+        #[rustfmt::skip]
+        let keysyms = vec![
+            0x61, 0x41, 0, 0, 0, 0,
+            0x65, 0x45, 0, 0, 0x20ac, 0,
+            0, 0, 0, 0, 0, 0,
+            0x6d1, 0x6f1, 0, 0, 0, 0,
+            0x31, 0x21, 0, 0, 0, 0,
+        ];
+        let mapping = KeyboardMapping {
+            min_keycode: 8,
+            keysyms_per_keycode: 6,
+            keysyms,
+        };
+        assert_eq!(mapping.find('a', true, false), Some((8, Level::Base)));
+        assert_eq!(mapping.find('E', true, false), Some((9, Level::Shift)));
+        assert_eq!(mapping.find('€', true, false), Some((9, Level::AltGr)));
+        assert_eq!(mapping.find('€', false, false), None);
+        assert_eq!(mapping.find('я', true, false), Some((11, Level::Base)));
+        assert_eq!(mapping.find('Я', true, false), Some((11, Level::Shift)));
+        assert_eq!(mapping.find('ü', true, false), None);
+        assert_eq!(mapping.spare_keycodes(), vec![10]);
+        assert_eq!(mapping.original(10), &[NO_SYMBOL; 6]);
+
+        // Caps Lock only swaps letters
+        assert_eq!(mapping.find('a', true, true), Some((8, Level::Shift)));
+        assert_eq!(mapping.find('A', true, true), Some((8, Level::Base)));
+        assert_eq!(mapping.find('я', true, true), Some((11, Level::Shift)));
+        assert_eq!(mapping.find('€', true, true), Some((9, Level::AltGr)));
+        assert_eq!(mapping.find('1', true, true), Some((12, Level::Base)));
+        assert_eq!(mapping.find('!', true, true), Some((12, Level::Shift)));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::linux::evdev_common::{
+    enable_rdev_events, rdev_event_to_evdev_events, OutputDevice, SIMULATED_DEVICE_NAME,
+};
+use crate::rdev::{EventType, SimulateError};
+use evdev_rs::{
+    enums::{EventCode, EV_ABS, EV_KEY, EV_SYN},
+    AbsInfo, Device, InputEvent, TimeVal, UInputDevice,
+};
+use std::io;
+use std::os::raw::c_int;
+use std::thread;
+use std::time::Duration;
+
+/// Sends events through a virtual keyboard+mouse, plus a virtual absolute
+/// pointer for `MouseMove`, since rdev coordinates are absolute and evdev
+/// mice are relative.
+pub struct UinputSimulator {
+    device: OutputDevice,
+    /// `None` when we don't know the screen size.
+    absolute: Option<OutputDevice>,
+}
+
+impl UinputSimulator {
+    pub fn new(screen_size: Option<(u64, u64)>) -> Result<UinputSimulator, SimulateError> {
+        let device = create_device().map_err(device_error)?;
+        let absolute = match screen_size {
+            Some((width, height)) => {
+                Some(create_absolute_device(width, height).map_err(device_error)?)
+            }
+            None => None,
+        };
+        // Give udev and the compositor some time to pick the new devices up,
+        // or the first events go nowhere.
+        thread::sleep(Duration::from_millis(200));
+        Ok(UinputSimulator { device, absolute })
+    }
+
+    /// `MouseMove` needs the screen size.
+    pub fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        let time = TimeVal::new(0, 0);
+        if let EventType::MouseMove { x, y } = event_type {
+            let absolute = self
+                .absolute
+                .as_mut()
+                .ok_or(SimulateError::UnsupportedEvent)?;
+            let mut events = vec![];
+            for (code, value) in &[(EV_ABS::ABS_X, x), (EV_ABS::ABS_Y, y)] {
+                let value = if value.is_finite() {
+                    value.round().max(0.0).min(c_int::MAX.into()) as c_int
+                } else {
+                    0
+                };
+                events.push(InputEvent::new(
+                    &time,
+                    &EventCode::EV_ABS(code.clone()),
+                    value,
+                ));
+            }
+            events.push(sync_event(&time));
+            return write_events(absolute, &events);
+        }
+        // The pointer position only matters for absolute motion, done above
+        let mut events = rdev_event_to_evdev_events(event_type, &time, &mut 0.0, &mut 0.0).ok_or(
+            match event_type {
+                EventType::KeyPress(key) | EventType::KeyRelease(key) => {
+                    SimulateError::UnmappedKey(*key)
+                }
+                _ => SimulateError::UnsupportedEvent,
+            },
+        )?;
+        events.push(sync_event(&time));
+        write_events(&mut self.device, &events)
+    }
+}
+
+fn write_events(device: &mut OutputDevice, events: &[InputEvent]) -> Result<(), SimulateError> {
+    events
+        .iter()
+        .try_for_each(|event| device.write_event(event))
+        .map_err(SimulateError::DeviceWrite)
+}
+
+fn device_error(error: io::Error) -> SimulateError {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => SimulateError::PermissionDenied,
+        _ => SimulateError::DeviceWrite(error),
+    }
+}
+
+/// The virtual keyboard+mouse.
+fn create_device() -> io::Result<OutputDevice> {
+    // libevdev_new only fails when it can't allocate
+    let device = Device::new().ok_or_else(|| io::Error::from_raw_os_error(libc::ENOMEM))?;
+    device.set_name(SIMULATED_DEVICE_NAME);
+    enable_rdev_events(&device)?;
+    Ok(OutputDevice::new(UInputDevice::create_from_device(
+        &device,
+    )?))
+}
+
+/// An absolute pointer whose axes match the screen pixels, values past the
+/// edges are clamped by the kernel.
+fn create_absolute_device(width: u64, height: u64) -> io::Result<OutputDevice> {
+    let device = Device::new().ok_or_else(|| io::Error::from_raw_os_error(libc::ENOMEM))?;
+    device.set_name(&format!("{} (absolute)", SIMULATED_DEVICE_NAME));
+    for (code, size) in &[(EV_ABS::ABS_X, width), (EV_ABS::ABS_Y, height)] {
+        let abs_info = AbsInfo {
+            value: 0,
+            minimum: 0,
+            maximum: (*size as c_int).saturating_sub(1),
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        };
+        device.enable_event_code(&EventCode::EV_ABS(code.clone()), Some(&abs_info))?;
+    }
+    // Without buttons, it's not a pointer for libinput
+    for button in &[EV_KEY::BTN_LEFT, EV_KEY::BTN_RIGHT, EV_KEY::BTN_MIDDLE] {
+        device.enable(&EventCode::EV_KEY(button.clone()))?;
+    }
+    Ok(OutputDevice::new(UInputDevice::create_from_device(
+        &device,
+    )?))
+}
+
+fn sync_event(time: &TimeVal) -> InputEvent {
+    InputEvent::new(time, &EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
+}
@@ -0,0 +1,266 @@
+use crate::linux::compose::Compose;
+use crate::linux::keycodes::code_from_key;
+use crate::rdev::{EventType, KeyboardState};
+use xkbcommon::xkb;
+
+/// Keyboard state backed by libxkbcommon instead of the X server, so no
+/// display is needed: it works under Wayland, in a console, headless or in CI.
+/// Dead keys and Compose sequences follow the locale's Compose file and
+/// `~/.XCompose`, like applications do.
+///
+/// ```no_run
+/// use rdev::{EventType, Key, KeyboardState, XkbKeyboard};
+///
+/// let mut keyboard = XkbKeyboard::from_layout("de(nodeadkeys)").unwrap();
+/// let name = keyboard.add(&EventType::KeyPress(Key::KeyY));
+/// assert_eq!(name, Some("z".to_string()));
+/// ```
+pub struct XkbKeyboard {
+    keymap: xkb::Keymap,
+    state: xkb::State,
+    compose: Option<Compose>,
+}
+
+impl XkbKeyboard {
+    /// The keymap is compiled from the `XKB_DEFAULT_RULES`,
+    /// `XKB_DEFAULT_MODEL`, `XKB_DEFAULT_LAYOUT`, `XKB_DEFAULT_VARIANT` and
+    /// `XKB_DEFAULT_OPTIONS` environment variables, or the system defaults.
+    pub fn new() -> Option<XkbKeyboard> {
+        XkbKeyboard::from_names("", "", "", "", None)
+    }
+
+    /// Compiles the keymap from RMLVO names, as in `setxkbmap` or the
+    /// `XKB_DEFAULT_*` variables. Empty names take the default.
+    ///
+    /// ```no_run
+    /// use rdev::XkbKeyboard;
+    ///
+    /// let keyboard = XkbKeyboard::from_names("evdev", "pc105", "fr", "", Some("caps:escape"));
+    /// ```
+    pub fn from_names(
+        rules: &str,
+        model: &str,
+        layout: &str,
+        variant: &str,
+        options: Option<&str>,
+    ) -> Option<XkbKeyboard> {
+        // xkbcommon panics on those
+        let names = [rules, model, layout, variant, options.unwrap_or("")];
+        if names.iter().any(|name| name.contains('\0')) {
+            return None;
+        }
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            rules,
+            model,
+            layout,
+            variant,
+            options.map(String::from),
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )?;
+        let state = xkb::State::new(&keymap);
+        Some(XkbKeyboard {
+            keymap,
+            state,
+            compose: Compose::from_env(),
+        })
+    }
+
+    /// Compiles the keymap for `layout`, written like `us`, `de(nodeadkeys)`
+    /// or `us,fr(azerty)` for several groups.
+    pub fn from_layout(layout: &str) -> Option<XkbKeyboard> {
+        let (layout, variant) = split_layout(layout)?;
+        XkbKeyboard::from_names("", "", &layout, &variant, None)
+    }
+
+    /// Uses the Compose file of `locale` (like `fr_FR.UTF-8`) instead of the
+    /// one from the environment. `None` when the locale has none.
+    pub fn with_compose_locale(mut self, locale: &str) -> Option<XkbKeyboard> {
+        self.compose = Some(Compose::from_locale(locale)?);
+        Some(self)
+    }
+
+    /// Turns dead keys and Compose off: dead keys give `None`, and what
+    /// follows them is left as is.
+    pub fn without_compose(mut self) -> XkbKeyboard {
+        self.compose = None;
+        self
+    }
+}
+
+/// `de(nodeadkeys),us` to the `de,us` layout and `nodeadkeys,` variant.
+fn split_layout(layout: &str) -> Option<(String, String)> {
+    let mut layouts = vec![];
+    let mut variants = vec![];
+    for group in layout.split(',') {
+        let group = group.trim();
+        match group.find('(') {
+            Some(start) => {
+                let variant = group[start + 1..].strip_suffix(')')?;
+                layouts.push(group[..start].trim());
+                variants.push(variant.trim());
+            }
+            None => {
+                layouts.push(group);
+                variants.push("");
+            }
+        }
+    }
+    Some((layouts.join(","), variants.join(",")))
+}
+
+impl KeyboardState for XkbKeyboard {
+    fn add(&mut self, event_type: &EventType) -> Option<String> {
+        match event_type {
+            EventType::KeyPress(key) => {
+                // X11 keycodes and xkbcommon keycodes are the same thing
+                // (evdev code + 8), so we can reuse the X11 table.
+                let keycode = xkb::Keycode::new(code_from_key(*key)?);
+                // Look the key up *before* updating the state, so that
+                // the key doesn't modify itself.
+                let name = self.state.key_get_utf8(keycode);
+                let keysym = self.state.key_get_one_sym(keycode);
+                self.state.update_key(keycode, xkb::KeyDirection::Down);
+                let name = if name.is_empty() { None } else { Some(name) };
+                match &mut self.compose {
+                    Some(compose) => compose.feed(keysym.raw(), name),
+                    None => name,
+                }
+            }
+            EventType::KeyRelease(key) => {
+                let keycode = xkb::Keycode::new(code_from_key(*key)?);
+                self.state.update_key(keycode, xkb::KeyDirection::Up);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = xkb::State::new(&self.keymap);
+        if let Some(compose) = &mut self.compose {
+            compose.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_layout, XkbKeyboard};
+    use crate::rdev::{EventType, Key, KeyboardState};
+
+    fn tap(keyboard: &mut XkbKeyboard, key: Key) -> Option<String> {
+        let name = keyboard.add(&EventType::KeyPress(key));
+        assert_eq!(keyboard.add(&EventType::KeyRelease(key)), None);
+        name
+    }
+
+    fn with(keyboard: &mut XkbKeyboard, modifier: Key, key: Key) -> Option<String> {
+        keyboard.add(&EventType::KeyPress(modifier));
+        let name = tap(keyboard, key);
+        keyboard.add(&EventType::KeyRelease(modifier));
+        name
+    }
+
+    #[test]
+    fn test_split_layout() {
+        let split = |layout| split_layout(layout).unwrap();
+        assert_eq!(split("us"), ("us".into(), "".into()));
+        assert_eq!(split("de(nodeadkeys)"), ("de".into(), "nodeadkeys".into()));
+        assert_eq!(
+            split("de(nodeadkeys), us"),
+            ("de,us".into(), "nodeadkeys,".into())
+        );
+        assert_eq!(split_layout("de(nodeadkeys"), None);
+    }
+
+    #[test]
+    fn test_us() {
+        let mut keyboard = XkbKeyboard::from_layout("us").unwrap();
+        assert_eq!(tap(&mut keyboard, Key::KeyS), Some("s".into()));
+        assert_eq!(
+            with(&mut keyboard, Key::ShiftLeft, Key::KeyS),
+            Some("S".into())
+        );
+        assert_eq!(
+            with(&mut keyboard, Key::ShiftRight, Key::Num2),
+            Some("@".into())
+        );
+        assert_eq!(tap(&mut keyboard, Key::ShiftLeft), None);
+
+        // Reset
+        keyboard.add(&EventType::KeyPress(Key::ShiftLeft));
+        keyboard.reset();
+        assert_eq!(tap(&mut keyboard, Key::KeyS), Some("s".into()));
+
+        // CapsLock
+        tap(&mut keyboard, Key::CapsLock);
+        assert_eq!(tap(&mut keyboard, Key::KeyC), Some("C".into()));
+        tap(&mut keyboard, Key::CapsLock);
+        assert_eq!(tap(&mut keyboard, Key::KeyC), Some("c".into()));
+    }
+
+    #[test]
+    fn test_de() {
+        let mut keyboard = XkbKeyboard::from_layout("de(nodeadkeys)").unwrap();
+        assert_eq!(tap(&mut keyboard, Key::KeyY), Some("z".into()));
+        assert_eq!(tap(&mut keyboard, Key::SemiColon), Some("ö".into()));
+        assert_eq!(
+            with(&mut keyboard, Key::ShiftLeft, Key::Num3),
+            Some("§".into())
+        );
+        assert_eq!(with(&mut keyboard, Key::AltGr, Key::KeyQ), Some("@".into()));
+        assert_eq!(with(&mut keyboard, Key::AltGr, Key::KeyE), Some("€".into()));
+        // A plain accent with nodeadkeys
+        assert_eq!(tap(&mut keyboard, Key::BackQuote), Some("^".into()));
+    }
+
+    #[test]
+    fn test_fr() {
+        let mut keyboard = XkbKeyboard::from_layout("fr").unwrap();
+        assert_eq!(tap(&mut keyboard, Key::KeyQ), Some("a".into()));
+        assert_eq!(tap(&mut keyboard, Key::Num2), Some("é".into()));
+        assert_eq!(
+            with(&mut keyboard, Key::ShiftLeft, Key::Num2),
+            Some("2".into())
+        );
+        assert_eq!(with(&mut keyboard, Key::AltGr, Key::Num0), Some("@".into()));
+    }
+
+    #[test]
+    fn test_dead_keys() {
+        let keyboard = XkbKeyboard::from_layout("fr").unwrap();
+        let mut keyboard = keyboard.with_compose_locale("fr_FR.UTF-8").unwrap();
+        // ^ then e
+        assert_eq!(tap(&mut keyboard, Key::LeftBracket), None);
+        assert_eq!(tap(&mut keyboard, Key::KeyE), Some("ê".into()));
+        // ¨ then e
+        assert_eq!(with(&mut keyboard, Key::ShiftLeft, Key::LeftBracket), None);
+        assert_eq!(tap(&mut keyboard, Key::KeyE), Some("ë".into()));
+        assert_eq!(tap(&mut keyboard, Key::KeyE), Some("e".into()));
+
+        let keyboard = XkbKeyboard::from_layout("es").unwrap();
+        let mut keyboard = keyboard.with_compose_locale("es_ES.UTF-8").unwrap();
+        // AltGr+ñ is ~
+        assert_eq!(with(&mut keyboard, Key::AltGr, Key::SemiColon), None);
+        assert_eq!(tap(&mut keyboard, Key::KeyN), Some("ñ".into()));
+        // ´ then Shift+e
+        assert_eq!(tap(&mut keyboard, Key::Quote), None);
+        assert_eq!(
+            with(&mut keyboard, Key::ShiftLeft, Key::KeyE),
+            Some("É".into())
+        );
+
+        let keyboard = XkbKeyboard::from_layout("fr").unwrap();
+        let mut keyboard = keyboard.without_compose();
+        assert_eq!(tap(&mut keyboard, Key::LeftBracket), None);
+        assert_eq!(tap(&mut keyboard, Key::KeyE), Some("e".into()));
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        assert!(XkbKeyboard::from_layout("not-a-layout").is_none());
+        assert!(XkbKeyboard::from_layout("us\0").is_none());
+    }
+}
@@ -147,10 +147,14 @@ pub unsafe fn convert(
             }
             _ => None,
         };
+        // Events from the hardware have no source process
+        let synthetic =
+            cg_event.get_integer_value_field(EventField::EVENT_SOURCE_UNIX_PROCESS_ID) != 0;
         return Some(Event {
             event_type,
             time: SystemTime::now(),
             name,
+            synthetic,
         });
     }
     None
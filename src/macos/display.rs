@@ -1,7 +1,15 @@
 use crate::rdev::DisplayError;
 use core_graphics::display::CGDisplay;
+use core_graphics::event::CGEvent;
+use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
 
 pub fn display_size() -> Result<(u64, u64), DisplayError> {
     let main = CGDisplay::main();
     Ok((main.pixels_wide(), main.pixels_high()))
 }
+
+pub fn mouse_position() -> Option<(f64, f64)> {
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).ok()?;
+    let location = CGEvent::new(source).ok()?.location();
+    Some((location.x, location.y))
+}
@@ -7,9 +7,9 @@ mod keycodes;
 mod listen;
 mod simulate;
 
-pub use crate::macos::display::display_size;
+pub use crate::macos::display::{display_size, mouse_position};
 #[cfg(feature = "unstable_grab")]
 pub use crate::macos::grab::grab;
 pub use crate::macos::keyboard::Keyboard;
 pub use crate::macos::listen::listen;
-pub use crate::macos::simulate::simulate;
+pub use crate::macos::simulate::{double_click_interval, simulate};
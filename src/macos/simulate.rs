@@ -1,13 +1,69 @@
 use crate::rdev::{Button, EventType, SimulateError};
 use core_graphics::event::{
-    CGEvent, CGEventTapLocation, CGEventType, CGMouseButton, ScrollEventUnit,
+    CGEvent, CGEventTapLocation, CGEventType, CGMouseButton, EventField, ScrollEventUnit,
 };
 use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
 use core_graphics::geometry::CGPoint;
+use lazy_static::lazy_static;
+use objc::{class, msg_send, sel, sel_impl};
 use std::convert::TryInto;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use crate::macos::keycodes::code_from_key;
 
+/// How far the pointer can move between two clicks of a double-click.
+const CLICK_DISTANCE: f64 = 4.0;
+
+/// The last button press, and how many clicks in a row it made.
+struct LastPress {
+    button: Button,
+    time: Instant,
+    point: CGPoint,
+    count: i64,
+}
+
+lazy_static! {
+    static ref LAST_PRESS: Mutex<Option<LastPress>> = Mutex::new(None);
+}
+
+/// The user's double-click time, from the mouse settings.
+pub fn double_click_interval() -> Duration {
+    let seconds: f64 = unsafe { msg_send![class!(NSEvent), doubleClickInterval] };
+    if seconds.is_finite() && seconds > 0.0 {
+        Duration::from_secs_f64(seconds)
+    } else {
+        Duration::from_millis(500)
+    }
+}
+
+/// Applications only see a double-click when the second press says so
+/// (`kCGMouseEventClickState`), the system doesn't count posted clicks for
+/// us. Counts them like it does for real ones: same button, close enough
+/// in time and space.
+fn click_count(button: Button, point: CGPoint, press: bool) -> i64 {
+    let mut last = LAST_PRESS.lock().unwrap();
+    let follows = last.as_ref().filter(|last| {
+        last.button == button
+            && (last.point.x - point.x).abs() <= CLICK_DISTANCE
+            && (last.point.y - point.y).abs() <= CLICK_DISTANCE
+    });
+    if !press {
+        return follows.map_or(1, |last| last.count);
+    }
+    let count = match follows {
+        Some(last) if last.time.elapsed() <= double_click_interval() => last.count + 1,
+        _ => 1,
+    };
+    *last = Some(LastPress {
+        button,
+        time: Instant::now(),
+        point,
+        count,
+    });
+    count
+}
+
 unsafe fn convert_native_with_source(
     event_type: &EventType,
     source: CGEventSource,
@@ -28,13 +84,16 @@ unsafe fn convert_native_with_source(
                 Button::Right => CGEventType::RightMouseDown,
                 _ => return None,
             };
-            CGEvent::new_mouse_event(
+            let event = CGEvent::new_mouse_event(
                 source,
                 event,
                 point,
                 CGMouseButton::Left, // ignored because we don't use OtherMouse EventType
             )
-            .ok()
+            .ok()?;
+            let count = click_count(*button, point, true);
+            event.set_integer_value_field(EventField::MOUSE_EVENT_CLICK_STATE, count);
+            Some(event)
         }
         EventType::ButtonRelease(button) => {
             let point = get_current_mouse_location()?;
@@ -43,19 +102,31 @@ unsafe fn convert_native_with_source(
                 Button::Right => CGEventType::RightMouseUp,
                 _ => return None,
             };
-            CGEvent::new_mouse_event(
+            let event = CGEvent::new_mouse_event(
                 source,
                 event,
                 point,
                 CGMouseButton::Left, // ignored because we don't use OtherMouse EventType
             )
-            .ok()
+            .ok()?;
+            let count = click_count(*button, point, false);
+            event.set_integer_value_field(EventField::MOUSE_EVENT_CLICK_STATE, count);
+            Some(event)
         }
         EventType::MouseMove { x, y } => {
             let point = CGPoint { x: (*x), y: (*y) };
             CGEvent::new_mouse_event(source, CGEventType::MouseMoved, point, CGMouseButton::Left)
                 .ok()
         }
+        EventType::MouseMoveRelative { dx, dy } => {
+            let point = get_current_mouse_location()?;
+            let point = CGPoint {
+                x: point.x + dx,
+                y: point.y + dy,
+            };
+            CGEvent::new_mouse_event(source, CGEventType::MouseMoved, point, CGMouseButton::Left)
+                .ok()
+        }
         EventType::Wheel { delta_x, delta_y } => {
             let wheel_count = 2;
             CGEvent::new_scroll_event(
@@ -86,12 +157,15 @@ unsafe fn get_current_mouse_location() -> Option<CGPoint> {
 extern "C" {}
 
 pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
+    if let EventType::KeyPress(key) | EventType::KeyRelease(key) = event_type {
+        code_from_key(*key).ok_or(SimulateError::UnmappedKey(*key))?;
+    }
     unsafe {
         if let Some(cg_event) = convert_native(event_type) {
             cg_event.post(CGEventTapLocation::HID);
             Ok(())
         } else {
-            Err(SimulateError)
+            Err(SimulateError::UnsupportedEvent)
         }
     }
 }
@@ -33,6 +33,14 @@ pub enum ListenError {
     KeyHookError(u32),
     /// Windows
     MouseHookError(u32),
+    /// Linux, evdev backend
+    IoError(std::io::Error),
+    /// Linux, evdev backend, none of the input devices could be opened, this
+    /// is the error for the last one
+    DeviceError {
+        path: std::path::PathBuf,
+        error: std::io::Error,
+    },
 }
 
 /// Errors that occur when trying to grab OS events.
@@ -54,8 +62,16 @@ pub enum GrabError {
     /// Windows
     MouseHookError(u32),
     /// All
-    SimulateError,
+    SimulateError(SimulateError),
     IoError(std::io::Error),
+    /// Linux, a device couldn't be grabbed in strict mode, or none of them
+    /// could be grabbed (the last failure)
+    DeviceError {
+        path: std::path::PathBuf,
+        error: std::io::Error,
+    },
+    /// Linux, creating the uinput devices events are let through to failed
+    UinputError(std::io::Error),
 }
 /// Errors that occur when trying to get display size.
 #[non_exhaustive]
@@ -65,9 +81,98 @@ pub enum DisplayError {
     ConversionError,
 }
 
+// The wrapped errors are only returned by `source`, printing them here too
+// would show them twice in error chains.
+impl Display for ListenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListenError::EventTapError => write!(f, "Could not create the event tap"),
+            ListenError::LoopSourceError => write!(f, "Could not create the run loop source"),
+            ListenError::MissingDisplayError => write!(f, "Could not open the X display"),
+            ListenError::KeyboardError => write!(f, "Could not set up the keyboard state"),
+            ListenError::RecordContextEnablingError => {
+                write!(f, "Could not enable the XRecord context")
+            }
+            ListenError::RecordContextError => write!(f, "Could not create the XRecord context"),
+            ListenError::XRecordExtensionError => {
+                write!(f, "The X server lacks the RECORD extension")
+            }
+            ListenError::KeyHookError(code) => {
+                write!(f, "Could not set the keyboard hook (error {})", code)
+            }
+            ListenError::MouseHookError(code) => {
+                write!(f, "Could not set the mouse hook (error {})", code)
+            }
+            ListenError::IoError(_) => write!(f, "Input/output error while listening"),
+            ListenError::DeviceError { path, .. } => {
+                write!(f, "Could not open {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ListenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ListenError::IoError(err) => Some(err),
+            ListenError::DeviceError { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for GrabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GrabError::EventTapError => write!(f, "Could not create the event tap"),
+            GrabError::LoopSourceError => write!(f, "Could not create the run loop source"),
+            GrabError::MissingDisplayError => write!(f, "Could not open the X display"),
+            GrabError::KeyboardError => write!(f, "Could not set up the keyboard state"),
+            GrabError::KeyHookError(code) => {
+                write!(f, "Could not set the keyboard hook (error {})", code)
+            }
+            GrabError::MouseHookError(code) => {
+                write!(f, "Could not set the mouse hook (error {})", code)
+            }
+            GrabError::SimulateError(_) => write!(f, "Could not send the grabbed event"),
+            GrabError::IoError(_) => write!(f, "Input/output error while grabbing"),
+            GrabError::DeviceError { path, .. } => write!(f, "Could not grab {}", path.display()),
+            GrabError::UinputError(_) => write!(f, "Could not create the uinput devices"),
+        }
+    }
+}
+
+impl std::error::Error for GrabError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GrabError::SimulateError(err) => Some(err),
+            GrabError::IoError(err) | GrabError::UinputError(err) => Some(err),
+            GrabError::DeviceError { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisplayError::NoDisplay => write!(f, "No display found"),
+            DisplayError::ConversionError => write!(f, "Display size out of range"),
+        }
+    }
+}
+
+impl std::error::Error for DisplayError {}
+
 impl From<SimulateError> for GrabError {
-    fn from(_: SimulateError) -> GrabError {
-        GrabError::SimulateError
+    fn from(err: SimulateError) -> GrabError {
+        GrabError::SimulateError(err)
+    }
+}
+
+impl From<std::io::Error> for ListenError {
+    fn from(err: std::io::Error) -> ListenError {
+        ListenError::IoError(err)
     }
 }
 
@@ -77,17 +182,57 @@ impl From<std::io::Error> for GrabError {
     }
 }
 
-/// Marking an error when we tried to simulate and event
+/// Errors that occur when trying to simulate an event.
 #[derive(Debug)]
-pub struct SimulateError;
+#[non_exhaustive]
+pub enum SimulateError {
+    /// Linux, no X server to connect to
+    NoDisplay,
+    /// Linux, the X server lacks the XTest extension, or rejected the event
+    XTestUnavailable,
+    /// The key has no key code on this OS
+    UnmappedKey(Key),
+    /// The event can't be simulated here, like a mouse button the OS has no
+    /// name for, or text the keyboard can't produce
+    UnsupportedEvent,
+    /// Linux, writing to the uinput device failed
+    DeviceWrite(std::io::Error),
+    /// Linux: no write access to /dev/uinput.
+    /// Windows: `SendInput` was blocked, by UIPI for instance.
+    PermissionDenied,
+}
 
 impl Display for SimulateError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Could not simulate event")
+        match self {
+            SimulateError::NoDisplay => write!(f, "Could not simulate event: no display"),
+            SimulateError::XTestUnavailable => {
+                write!(f, "Could not simulate event: XTest is unavailable")
+            }
+            SimulateError::UnmappedKey(key) => {
+                write!(f, "Could not simulate event: {:?} has no key code", key)
+            }
+            SimulateError::UnsupportedEvent => {
+                write!(f, "Could not simulate event: unsupported event")
+            }
+            SimulateError::DeviceWrite(_) => {
+                write!(f, "Could not simulate event: device write failed")
+            }
+            SimulateError::PermissionDenied => {
+                write!(f, "Could not simulate event: permission denied")
+            }
+        }
     }
 }
 
-impl std::error::Error for SimulateError {}
+impl std::error::Error for SimulateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SimulateError::DeviceWrite(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 /// Key names based on physical location on the device
 /// Merge Option(MacOS) and Alt(Windows, Linux) into Alt
@@ -241,6 +386,12 @@ pub enum EventType {
         x: f64,
         y: f64,
     },
+    /// Moves the pointer by `dx`, `dy` pixels from wherever it is. Only ever
+    /// simulated, listening always gives absolute `MouseMove`s.
+    MouseMoveRelative {
+        dx: f64,
+        dy: f64,
+    },
     /// `delta_y` represents vertical scroll and `delta_x` represents horizontal scroll.
     /// Positive values correspond to scrolling up or right and negative values
     /// correspond to scrolling down or left
@@ -254,14 +405,21 @@ pub enum EventType {
 /// EventType, which is the time when this event was received, and the name Option
 /// which contains what characters should be emmitted from that event. This relies
 /// on the OS layout and keyboard state machinery.
-/// Caveat: Dead keys don't function on Linux(X11) yet. You will receive None for
-/// a dead key, and the raw letter instead of accentuated letter.
+/// Caveat: On Linux, dead keys and Compose sequences need the `xkb` feature.
+/// Without it, you will receive None for a dead key, and the raw letter instead
+/// of the accentuated letter.
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct Event {
     pub time: SystemTime,
     pub name: Option<String>,
     pub event_type: EventType,
+    /// The event was injected by a program rather than coming from an actual
+    /// device: through XTest on X11 (`simulate`, but also xdotool and the
+    /// like), from rdev's own uinput device with evdev, `SendInput` on
+    /// Windows, and any other process on macOS.
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub synthetic: bool,
 }
 
 /// We can define a dummy Keyboard, that we will use to detect
@@ -269,7 +427,7 @@ pub struct Event {
 /// layout for now !
 /// Caveat : This is layout dependent. If your app needs to support
 /// layout switching don't use this !
-/// Caveat: On Linux, the dead keys mechanism is not implemented.
+/// Caveat: On Linux, dead keys and Compose sequences need the `xkb` feature.
 /// Caveat: Only shift and dead keys are implemented, Alt+unicode code on windows
 /// won't work.
 ///
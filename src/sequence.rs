@@ -0,0 +1,346 @@
+use crate::rdev::{EventType, Key, SimulateError};
+use crate::simulate;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// An event to simulate, and how long to wait after sending it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Step {
+    pub event_type: EventType,
+    pub delay: Duration,
+}
+
+impl Step {
+    pub fn new(event_type: EventType, delay: Duration) -> Step {
+        Step { event_type, delay }
+    }
+}
+
+/// Simulates every step in order, waiting `step.delay` after each one.
+/// Stops at the first event that can't be sent.
+///
+/// ```no_run
+/// use rdev::{simulate_sequence, EventType, Key, Step};
+/// use std::time::Duration;
+///
+/// let delay = Duration::from_millis(20);
+/// simulate_sequence(&[
+///     Step::new(EventType::KeyPress(Key::KeyS), delay),
+///     Step::new(EventType::KeyRelease(Key::KeyS), delay),
+/// ])
+/// .unwrap();
+/// ```
+pub fn simulate_sequence(steps: &[Step]) -> Result<(), SimulateError> {
+    for step in steps {
+        simulate(&step.event_type)?;
+        thread::sleep(step.delay);
+    }
+    Ok(())
+}
+
+/// How `typing_steps` spaces key presses out.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TypingOptions {
+    /// Typing speed in words per minute, a word being 5 keys.
+    pub wpm: f64,
+    /// How much each delay can randomly vary, as a fraction of it: 0.2 means
+    /// anywhere between 80% and 120%.
+    pub jitter: f64,
+    /// How long each key is held down, before jitter.
+    pub hold: Duration,
+    /// No delay is ever shorter than this, whatever the speed and jitter.
+    pub min_gap: Duration,
+    /// Seed for the jitter, to replay the exact same timings. `None` seeds
+    /// from the clock.
+    pub seed: Option<u64>,
+}
+
+impl Default for TypingOptions {
+    fn default() -> TypingOptions {
+        TypingOptions {
+            wpm: 60.0,
+            jitter: 0.3,
+            hold: Duration::from_millis(50),
+            min_gap: Duration::from_millis(5),
+            seed: None,
+        }
+    }
+}
+
+/// The press and release steps to type `keys` like a person would, see
+/// `TypingOptions`. Keys start `60 / (wpm * 5)` seconds apart on average, and
+/// each one is held for `hold`.
+///
+/// ```no_run
+/// use rdev::{simulate_sequence, typing_steps, Key, TypingOptions};
+///
+/// let mut options = TypingOptions::default();
+/// options.wpm = 90.0;
+/// let steps = typing_steps(&[Key::KeyH, Key::KeyI], &options);
+/// simulate_sequence(&steps).unwrap();
+/// ```
+pub fn typing_steps(keys: &[Key], options: &TypingOptions) -> Vec<Step> {
+    let mut rng = Rng::new(options.seed.unwrap_or_else(clock_seed));
+    let interval = if options.wpm > 0.0 {
+        60.0 / (options.wpm * 5.0)
+    } else {
+        0.0
+    };
+    let jitter = options.jitter.clamp(0.0, 1.0);
+    let mut jittered = |secs: f64| {
+        let secs = secs * (1.0 + jitter * rng.next_signed());
+        let delay = Duration::from_secs_f64(secs.max(0.0));
+        delay.max(options.min_gap)
+    };
+
+    let hold = options.hold.as_secs_f64();
+    let mut steps = Vec::with_capacity(keys.len() * 2);
+    for key in keys {
+        let held = jittered(hold);
+        let released = jittered((interval - held.as_secs_f64()).max(0.0));
+        steps.push(Step::new(EventType::KeyPress(*key), held));
+        steps.push(Step::new(EventType::KeyRelease(*key), released));
+    }
+    steps
+}
+
+/// Types `keys` with the timings from `typing_steps`.
+pub fn simulate_typing(keys: &[Key], options: &TypingOptions) -> Result<(), SimulateError> {
+    simulate_sequence(&typing_steps(keys, options))
+}
+
+/// Keys held down until dropped, released in the reverse order they were
+/// pressed in. Lets a shortcut release its modifiers even when sending fails
+/// halfway, or when the code in between panics.
+///
+/// ```no_run
+/// use rdev::{simulate, EventType, HeldKeys, Key};
+///
+/// let shift = HeldKeys::press(&[Key::ShiftLeft]).unwrap();
+/// simulate(&EventType::KeyPress(Key::KeyA)).unwrap();
+/// simulate(&EventType::KeyRelease(Key::KeyA)).unwrap();
+/// // Releases Shift
+/// drop(shift);
+/// ```
+#[must_use = "the keys are released as soon as this is dropped"]
+pub struct HeldKeys {
+    keys: Vec<Key>,
+    send: fn(&EventType) -> Result<(), SimulateError>,
+}
+
+impl HeldKeys {
+    /// Presses `keys` in order. If one can't be pressed, the ones before it
+    /// are released again.
+    pub fn press(keys: &[Key]) -> Result<HeldKeys, SimulateError> {
+        HeldKeys::press_with(keys, simulate)
+    }
+
+    fn press_with(
+        keys: &[Key],
+        send: fn(&EventType) -> Result<(), SimulateError>,
+    ) -> Result<HeldKeys, SimulateError> {
+        let mut held = HeldKeys { keys: vec![], send };
+        for key in keys {
+            send(&EventType::KeyPress(*key))?;
+            held.keys.push(*key);
+        }
+        Ok(held)
+    }
+
+    /// Releases the keys now, reporting the first release that failed. All of
+    /// them are still tried.
+    pub fn release(mut self) -> Result<(), SimulateError> {
+        self.release_all()
+    }
+
+    fn release_all(&mut self) -> Result<(), SimulateError> {
+        let mut result = Ok(());
+        while let Some(key) = self.keys.pop() {
+            let released = (self.send)(&EventType::KeyRelease(key));
+            result = result.and(released);
+        }
+        result
+    }
+}
+
+impl Drop for HeldKeys {
+    fn drop(&mut self) {
+        self.release_all().ok();
+    }
+}
+
+/// Presses `modifiers` in order, taps `key`, then releases the modifiers in
+/// reverse order. Whatever was pressed is released even if sending fails.
+///
+/// ```no_run
+/// use rdev::{simulate_chord, Key};
+///
+/// // Reopen the last closed tab
+/// simulate_chord(&[Key::ControlLeft, Key::ShiftLeft], Key::KeyT).unwrap();
+/// ```
+pub fn simulate_chord(modifiers: &[Key], key: Key) -> Result<(), SimulateError> {
+    chord_with(modifiers, key, simulate)
+}
+
+fn chord_with(
+    modifiers: &[Key],
+    key: Key,
+    send: fn(&EventType) -> Result<(), SimulateError>,
+) -> Result<(), SimulateError> {
+    let modifiers = HeldKeys::press_with(modifiers, send)?;
+    let tapped = HeldKeys::press_with(&[key], send)?;
+    tapped.release()?;
+    modifiers.release()
+}
+
+/// A seed for when none is given.
+pub(crate) fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// xorshift64*, plenty for timings and saves a dependency.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // The state must never be 0
+        const MIX: u64 = 0x9e37_79b9_7f4a_7c15;
+        match seed ^ MIX {
+            0 => Rng(MIX),
+            state => Rng(state),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in [-1, 1].
+    pub(crate) fn next_signed(&mut self) -> f64 {
+        let unit = (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64;
+        unit * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{chord_with, typing_steps, Rng, Step, TypingOptions};
+    use crate::rdev::{EventType, Key, SimulateError};
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    thread_local! {
+        static SENT: RefCell<Vec<EventType>> = const { RefCell::new(vec![]) };
+    }
+
+    /// Records what is sent, and fails to press `KeyT`
+    fn record(event_type: &EventType) -> Result<(), SimulateError> {
+        SENT.with(|sent| sent.borrow_mut().push(*event_type));
+        match event_type {
+            EventType::KeyPress(Key::KeyT) => Err(SimulateError::UnmappedKey(Key::KeyT)),
+            _ => Ok(()),
+        }
+    }
+
+    fn sent() -> Vec<EventType> {
+        SENT.with(|sent| sent.borrow_mut().drain(..).collect())
+    }
+
+    fn options(jitter: f64) -> TypingOptions {
+        TypingOptions {
+            wpm: 60.0,
+            jitter,
+            hold: Duration::from_millis(50),
+            min_gap: Duration::from_millis(5),
+            seed: Some(42),
+        }
+    }
+
+    #[test]
+    fn test_typing_steps() {
+        // 60 wpm is 200ms per key
+        let steps = typing_steps(&[Key::KeyA, Key::KeyB], &options(0.0));
+        assert_eq!(
+            steps,
+            vec![
+                Step::new(EventType::KeyPress(Key::KeyA), Duration::from_millis(50)),
+                Step::new(EventType::KeyRelease(Key::KeyA), Duration::from_millis(150)),
+                Step::new(EventType::KeyPress(Key::KeyB), Duration::from_millis(50)),
+                Step::new(EventType::KeyRelease(Key::KeyB), Duration::from_millis(150)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_typing_jitter() {
+        let keys = [Key::KeyA; 100];
+        let steps = typing_steps(&keys, &options(0.5));
+        assert_eq!(steps, typing_steps(&keys, &options(0.5)));
+        for step in steps.chunks(2) {
+            assert!(step[0].delay >= Duration::from_millis(25));
+            assert!(step[0].delay <= Duration::from_millis(75));
+            assert!(step[1].delay >= Duration::from_millis(5));
+        }
+        let total: Duration = steps.iter().map(|step| step.delay).sum();
+        assert!(total > Duration::from_secs(15) && total < Duration::from_secs(25));
+
+        let mut fast = options(1.0);
+        fast.wpm = 10_000.0;
+        fast.min_gap = Duration::from_millis(10);
+        for step in typing_steps(&keys, &fast) {
+            assert!(step.delay >= Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_chord() {
+        use EventType::{KeyPress, KeyRelease};
+
+        let modifiers = [Key::ControlLeft, Key::ShiftLeft];
+        assert!(chord_with(&modifiers, Key::KeyA, record).is_ok());
+        assert_eq!(
+            sent(),
+            vec![
+                KeyPress(Key::ControlLeft),
+                KeyPress(Key::ShiftLeft),
+                KeyPress(Key::KeyA),
+                KeyRelease(Key::KeyA),
+                KeyRelease(Key::ShiftLeft),
+                KeyRelease(Key::ControlLeft),
+            ]
+        );
+
+        assert!(chord_with(&modifiers, Key::KeyT, record).is_err());
+        assert_eq!(
+            sent(),
+            vec![
+                KeyPress(Key::ControlLeft),
+                KeyPress(Key::ShiftLeft),
+                KeyPress(Key::KeyT),
+                KeyRelease(Key::ShiftLeft),
+                KeyRelease(Key::ControlLeft),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rng() {
+        let mut rng = Rng::new(0);
+        for _ in 0..1000 {
+            let value = rng.next_signed();
+            assert!((-1.0..=1.0).contains(&value));
+        }
+    }
+}
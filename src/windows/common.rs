@@ -11,10 +11,10 @@ use winapi::shared::ntdef::LONG;
 use winapi::shared::windef::HHOOK;
 use winapi::um::errhandlingapi::GetLastError;
 use winapi::um::winuser::{
-    SetWindowsHookExA, KBDLLHOOKSTRUCT, MSLLHOOKSTRUCT, WHEEL_DELTA, WH_KEYBOARD_LL, WH_MOUSE_LL,
-    WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP,
-    WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SYSKEYDOWN,
-    WM_SYSKEYUP, WM_XBUTTONDOWN, WM_XBUTTONUP,
+    SetWindowsHookExA, KBDLLHOOKSTRUCT, LLKHF_INJECTED, LLMHF_INJECTED, MSLLHOOKSTRUCT,
+    WHEEL_DELTA, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP,
+    WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN,
+    WM_RBUTTONUP, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_XBUTTONDOWN, WM_XBUTTONUP,
 };
 pub const TRUE: i32 = 1;
 pub const FALSE: i32 = 0;
@@ -48,6 +48,20 @@ pub unsafe fn get_button_code(lpdata: LPARAM) -> WORD {
     HIWORD(mouse.mouseData)
 }
 
+/// Whether the event comes from `SendInput` and the like.
+pub unsafe fn is_injected(param: WPARAM, lpdata: LPARAM) -> bool {
+    match param.try_into() {
+        Ok(WM_KEYDOWN) | Ok(WM_SYSKEYDOWN) | Ok(WM_KEYUP) | Ok(WM_SYSKEYUP) => {
+            let kb = *(lpdata as *const KBDLLHOOKSTRUCT);
+            kb.flags & LLKHF_INJECTED != 0
+        }
+        _ => {
+            let mouse = *(lpdata as *const MSLLHOOKSTRUCT);
+            mouse.flags & LLMHF_INJECTED != 0
+        }
+    }
+}
+
 pub unsafe fn convert(param: WPARAM, lpdata: LPARAM) -> Option<EventType> {
     match param.try_into() {
         Ok(WM_KEYDOWN) | Ok(WM_SYSKEYDOWN) => {
@@ -1,6 +1,7 @@
 use crate::rdev::DisplayError;
 use std::convert::TryInto;
-use winapi::um::winuser::{GetSystemMetrics, SM_CXSCREEN, SM_CYSCREEN};
+use winapi::shared::windef::POINT;
+use winapi::um::winuser::{GetCursorPos, GetSystemMetrics, SM_CXSCREEN, SM_CYSCREEN};
 
 pub fn display_size() -> Result<(u64, u64), DisplayError> {
     let w = unsafe {
@@ -15,3 +16,11 @@ pub fn display_size() -> Result<(u64, u64), DisplayError> {
     };
     Ok((w, h))
 }
+
+pub fn mouse_position() -> Option<(f64, f64)> {
+    let mut point = POINT { x: 0, y: 0 };
+    if unsafe { GetCursorPos(&mut point) } == 0 {
+        return None;
+    }
+    Some((point.x.into(), point.y.into()))
+}
@@ -1,5 +1,7 @@
 use crate::rdev::{Event, EventType, GrabError};
-use crate::windows::common::{convert, set_key_hook, set_mouse_hook, HookError, HOOK, KEYBOARD};
+use crate::windows::common::{
+    convert, is_injected, set_key_hook, set_mouse_hook, HookError, HOOK, KEYBOARD,
+};
 use std::ptr::null_mut;
 use std::time::SystemTime;
 use winapi::um::winuser::{CallNextHookEx, GetMessageA, HC_ACTION};
@@ -21,6 +23,7 @@ unsafe extern "system" fn raw_callback(code: i32, param: usize, lpdata: isize) -
                 event_type,
                 time: SystemTime::now(),
                 name,
+                synthetic: is_injected(param, lpdata),
             };
             if let Some(callback) = &mut GLOBAL_CALLBACK {
                 if callback(event).is_none() {
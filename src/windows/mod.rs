@@ -9,9 +9,9 @@ mod keycodes;
 mod listen;
 mod simulate;
 
-pub use crate::windows::display::display_size;
+pub use crate::windows::display::{display_size, mouse_position};
 #[cfg(feature = "unstable_grab")]
 pub use crate::windows::grab::grab;
 pub use crate::windows::keyboard::Keyboard;
 pub use crate::windows::listen::listen;
-pub use crate::windows::simulate::simulate;
+pub use crate::windows::simulate::{double_click_interval, simulate};
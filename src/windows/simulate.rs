@@ -2,12 +2,13 @@ use crate::rdev::{Button, EventType, SimulateError};
 use crate::windows::keycodes::code_from_key;
 use std::convert::TryFrom;
 use std::mem::size_of;
+use std::time::Duration;
 use winapi::ctypes::{c_int, c_short};
 use winapi::shared::minwindef::{DWORD, UINT, WORD};
 use winapi::shared::ntdef::LONG;
 use winapi::um::winuser::{
-    GetSystemMetrics, INPUT_u, SendInput, INPUT, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT,
-    KEYEVENTF_KEYUP, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_HWHEEL, MOUSEEVENTF_LEFTDOWN,
+    GetDoubleClickTime, GetSystemMetrics, INPUT_u, SendInput, INPUT, INPUT_KEYBOARD, INPUT_MOUSE,
+    KEYBDINPUT, KEYEVENTF_KEYUP, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_HWHEEL, MOUSEEVENTF_LEFTDOWN,
     MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE,
     MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_VIRTUALDESK, MOUSEEVENTF_WHEEL,
     MOUSEEVENTF_XDOWN, MOUSEEVENTF_XUP, MOUSEINPUT, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
@@ -38,8 +39,9 @@ fn sim_mouse_event(flags: DWORD, data: DWORD, dx: LONG, dy: LONG) -> Result<(),
             size_of::<INPUT>() as c_int,
         )
     };
+    // Blocked, most likely by UIPI for a window of a more privileged process
     if value != 1 {
-        Err(SimulateError)
+        Err(SimulateError::PermissionDenied)
     } else {
         Ok(())
     }
@@ -66,8 +68,9 @@ fn sim_keyboard_event(flags: DWORD, vk: WORD, scan: WORD) -> Result<(), Simulate
             size_of::<INPUT>() as c_int,
         )
     };
+    // Blocked, most likely by UIPI for a window of a more privileged process
     if value != 1 {
-        Err(SimulateError)
+        Err(SimulateError::PermissionDenied)
     } else {
         Ok(())
     }
@@ -76,11 +79,11 @@ fn sim_keyboard_event(flags: DWORD, vk: WORD, scan: WORD) -> Result<(), Simulate
 pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
     match event_type {
         EventType::KeyPress(key) => {
-            let code = code_from_key(*key).ok_or(SimulateError)?;
+            let code = code_from_key(*key).ok_or(SimulateError::UnmappedKey(*key))?;
             sim_keyboard_event(KEYEVENTF_KEYDOWN, code, 0)
         }
         EventType::KeyRelease(key) => {
-            let code = code_from_key(*key).ok_or(SimulateError)?;
+            let code = code_from_key(*key).ok_or(SimulateError::UnmappedKey(*key))?;
             sim_keyboard_event(KEYEVENTF_KEYUP, code, 0)
         }
         EventType::ButtonPress(button) => match button {
@@ -99,7 +102,8 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
             if *delta_x != 0 {
                 sim_mouse_event(
                     MOUSEEVENTF_HWHEEL,
-                    (c_short::try_from(*delta_x).map_err(|_| SimulateError)? * WHEEL_DELTA) as u32,
+                    (c_short::try_from(*delta_x).map_err(|_| SimulateError::UnsupportedEvent)?
+                        * WHEEL_DELTA) as u32,
                     0,
                     0,
                 )?;
@@ -108,7 +112,8 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
             if *delta_y != 0 {
                 sim_mouse_event(
                     MOUSEEVENTF_WHEEL,
-                    (c_short::try_from(*delta_y).map_err(|_| SimulateError)? * WHEEL_DELTA) as u32,
+                    (c_short::try_from(*delta_y).map_err(|_| SimulateError::UnsupportedEvent)?
+                        * WHEEL_DELTA) as u32,
                     0,
                     0,
                 )?;
@@ -119,7 +124,7 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
             let width = unsafe { GetSystemMetrics(SM_CXVIRTUALSCREEN) };
             let height = unsafe { GetSystemMetrics(SM_CYVIRTUALSCREEN) };
             if width == 0 || height == 0 {
-                return Err(SimulateError);
+                return Err(SimulateError::NoDisplay);
             }
 
             sim_mouse_event(
@@ -129,5 +134,14 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
                 (*y as i32 + 1) * 65535 / height,
             )
         }
+        // Subject to the pointer speed and acceleration settings
+        EventType::MouseMoveRelative { dx, dy } => {
+            sim_mouse_event(MOUSEEVENTF_MOVE, 0, *dx as i32, *dy as i32)
+        }
     }
 }
+
+/// The user's double-click time, from the mouse settings.
+pub fn double_click_interval() -> Duration {
+    Duration::from_millis(unsafe { GetDoubleClickTime() }.into())
+}
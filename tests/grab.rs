@@ -34,10 +34,6 @@ fn grab_tab(event: Event) -> Option<Event> {
 #[test]
 #[serial]
 fn test_grab() -> Result<(), Box<dyn Error>> {
-    // Wait for tester's key to go back up
-    // otherwise, test fails due to KeyRelease(Return)
-    thread::sleep(Duration::from_millis(300));
-
     // spawn new thread because listen blocks
     let _listener = thread::spawn(move || {
         listen(send_event).expect("Could not listen");
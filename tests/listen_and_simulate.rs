@@ -37,6 +37,7 @@ fn sim_then_listen(events: &mut dyn Iterator<Item = EventType>) -> Result<(), Bo
         simulate(&event)?;
         let recieved_event = recv.recv_timeout(second).expect("No events to recieve");
         assert_eq!(recieved_event.event_type, event);
+        assert!(recieved_event.synthetic);
     }
     Ok(())
 }